@@ -0,0 +1,61 @@
+use shared_lib::{assets::Asset, multi_tokens::ext_mt};
+
+use crate::*;
+
+const GAS_FOR_MT_TRANSFER: Gas = Gas::from_tgas(20);
+
+#[near_bindgen]
+impl EscrowDst {
+    // Funds an escrow paying native NEAR to the maker, the native counterpart of
+    // ft_on_transfer. The attached NEAR must cover taking_amount plus dst_safty_deposit.
+    // A wNEAR taking token can be funded this way too, the NEAR is wrapped when paid to the maker.
+    #[payable]
+    pub fn create_native_escrow(&mut self, immutables: Immutables, src_cancellation_timestamp: u32) {
+        let taker = env::predecessor_account_id();
+        require!(immutables.taking_token == Asset::Near || self.is_wrapped_near(&immutables.taking_token), "Invalid token...");
+
+        let required = immutables.taking_amount.checked_add(immutables.dst_safty_deposit)
+            .expect("Overflow when calculating required deposit");
+        require!(env::attached_deposit() == required, "Attached deposit must equal taking amount plus safety deposit...");
+
+        let immutables_hash = self.validate_new_escrow(&immutables, &taker, src_cancellation_timestamp);
+        self.store_escrow(immutables, immutables_hash, taker, Asset::Near);
+    }
+
+    // NEP-245 counterpart of ft_on_transfer: the msg is a hex-encoded DstEscrowParams whose taking_token
+    // is the Asset::Mt of the single transferred token id, sent by its owner (the taker)
+    pub fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<NearToken>,
+        msg: String
+    ) -> PromiseOrValue<Vec<NearToken>> {
+        require!(token_ids.len() == 1 && amounts.len() == 1, "Only one token id per transfer...");
+        require!(previous_owner_ids.len() == 1 && previous_owner_ids[0] == sender_id, "Tokens must be sent by their owner...");
+
+        let token = Asset::Mt { contract: env::predecessor_account_id(), token_id: token_ids[0].clone() };
+        let unused_amount = self.fund_transferred_escrow(sender_id, token, amounts[0], msg);
+
+        PromiseOrValue::Value(vec![unused_amount])
+    }
+}
+
+impl EscrowDst {
+    // Pays out an escrowed asset, native NEAR with a plain transfer, fungible tokens
+    // through safe_ft_transfer and multi tokens with mt_transfer
+    pub(crate) fn transfer_asset(&mut self, asset: &Asset, receiver_id: AccountId, amount: NearToken) -> Promise {
+        match asset.clone() {
+            Asset::Near => Promise::new(receiver_id).transfer(amount),
+            Asset::Ft(token_contract) => self.safe_ft_transfer(token_contract, receiver_id, amount),
+            Asset::Mt { contract, token_id } => ext_mt::ext(contract)
+                .with_attached_deposit(NearToken::from_yoctonear(1)) // 1 yoctoNEAR for mt_transfer
+                .with_static_gas(GAS_FOR_MT_TRANSFER)
+                .mt_transfer(receiver_id, token_id, amount, None, None),
+            // NFTs are only sold on the source escrow, none can be held here
+            Asset::Nft { .. } => env::panic_str("NFTs can't be escrowed on the destination chain..."),
+            Asset::Foreign(_) => env::panic_str("Tokens of another chain can't be paid out...")
+        }
+    }
+}
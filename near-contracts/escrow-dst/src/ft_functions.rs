@@ -1,12 +1,11 @@
 use near_sdk::{
-    env, near_bindgen, AccountId, Promise, Gas, ext_contract,
+    env, near_bindgen, AccountId, Promise, ext_contract,
     PromiseResult,
 };
 use shared_lib::fungible_tokens::{ext_ft, StorageBalance};
 
-const GAS_FOR_STORAGE_BALANCE_OF: Gas = Gas::from_tgas(10);
-const GAS_FOR_STORAGE_DEPOSIT: Gas = Gas::from_tgas(20);
-const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(15);
+use shared_lib::fungible_tokens::{GAS_FOR_FT_TRANSFER, GAS_FOR_ON_CHECK_STORAGE, GAS_FOR_ON_STORAGE_DEPOSIT, GAS_FOR_STORAGE_BALANCE_OF, GAS_FOR_STORAGE_DEPOSIT};
+
 const STORAGE_DEPOSIT_AMOUNT: u128 = 1250000000000000000000; // 0.00125 NEAR
 
 use crate::*;
@@ -43,7 +42,7 @@ impl EscrowDst {
             .storage_balance_of(receiver_id.clone())
             .then(
                 ext_self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_STORAGE_DEPOSIT)
+                    .with_static_gas(GAS_FOR_ON_CHECK_STORAGE)
                     .on_check_storage(token_contract, receiver_id, amount),
             )
     }
@@ -74,7 +73,7 @@ impl EscrowDst {
                         .storage_deposit(Some(receiver_id.clone()), Some(true))
                         .then(
                             ext_self::ext(env::current_account_id())
-                                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                                .with_static_gas(GAS_FOR_ON_STORAGE_DEPOSIT)
                                 .on_storage_deposit(token_contract, receiver_id, amount),
                         )
                 }
@@ -1,16 +1,42 @@
 use std::str::FromStr;
 
-use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, near_bindgen, require, serde::{Deserialize, Serialize}, store::LookupMap, AccountId, Gas, NearSchema, NearToken, Promise, PromiseOrValue};
-use shared_lib::{fungible_tokens::ext_ft, immutables::Immutables};
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, ext_contract, log, near_bindgen, require, serde::{Deserialize, Serialize}, store::{IterableMap, LookupMap}, AccountId, Gas, NearSchema, NearToken, Promise, PromiseError, PromiseOrValue};
+use shared_lib::{assets::Asset, escrow_status::EscrowStatus, events::{CancelledEvent, EscrowCreatedEvent, EscrowEvent, SafetyDepositEvent, WithdrawnEvent}, immutables::{Immutables, Stage, TimeLock}, near_wrapper::NearWrapper, resolver_registry::ResolverRegistry, secondary_index::SecondaryIndex, secret_registry::SecretRegistry};
 
 pub mod ft_functions;
+pub mod resolvers;
+pub mod secrets;
+pub mod views;
+pub mod assets;
+pub mod near_balances;
+pub mod wrap_near;
+pub mod migration;
+
+use migration::{LegacyResolverOrder, LEGACY_RESOLVERS_ORDERS_PREFIX};
+
+const GAS_FOR_RESOLVE_SETTLEMENT: Gas = Gas::from_tgas(15);
+
+#[ext_contract(ext_escrow)]
+trait _EscrowCallbacks {
+    fn on_settlement_resolved(&mut self, immutables_hash: String, safty_deposit_receiver: AccountId, event: EscrowEvent) -> bool;
+}
 
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
 #[serde(crate = "near_sdk::serde")]
 #[borsh(crate = "near_sdk::borsh")]
 pub struct ResolverOrder {
     pub immutables: Immutables,
-    pub safty_deposit: NearToken
+    pub safty_deposit: NearToken,
+    pub status: EscrowStatus,
+    pub held_asset: Asset           // asset locked by the taker, NEAR or wNEAR when it differs from taking_token
+}
+
+// msg of ft_on_transfer / mt_on_transfer funding an escrow, hex-encoded borsh
+#[derive(BorshSerialize, BorshDeserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct DstEscrowParams {
+    pub immutables: Immutables,
+    pub src_cancellation_timestamp: u32     // absolute src cancellation start (seconds), see TimeLock::verify_dst_cancellation
 }
 
 const ZERO_NEAR: NearToken = NearToken::from_yoctonear(0);
@@ -19,13 +45,41 @@ const ZERO_NEAR: NearToken = NearToken::from_yoctonear(0);
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 #[borsh(crate = "near_sdk::borsh")]
 pub struct EscrowDst {
-    pub resolvers_orders: LookupMap<String, ResolverOrder>
+    // escrows funded by resolvers
+    // delete entry once the escrow is withdrawn or cancelled
+    // entry key: resolver_order.immutables.hash()
+    pub resolvers_orders: IterableMap<String, ResolverOrder>,
+
+    // immutables hashes of resolvers_orders by taker
+    pub escrows_by_taker: SecondaryIndex<AccountId>,
+
+    // resolvers allowed to fund escrows, managed by the owner
+    pub resolver_registry: ResolverRegistry,
+
+    // NEAR pre-funded by resolvers for safety deposits
+    // entry key: resolver
+    pub near_balances: LookupMap<AccountId, NearToken>,
+
+    // secrets revealed by withdrawals, keyed by hashlock and by immutables hash
+    pub revealed_secrets: SecretRegistry,
+
+    // wNEAR contract escrows can be funded in for a NEAR taking token and vice versa, set by the owner
+    pub near_wrapper: NearWrapper,
+
+    // escrows of the first deployed layout, left at their old prefix (see migration.rs)
+    pub legacy_resolvers_orders: LookupMap<String, LegacyResolverOrder>
 }
 
 impl Default for EscrowDst {
     fn default() -> Self {
         Self {
-            resolvers_orders: LookupMap::new(b"r")
+            resolvers_orders: IterableMap::new(b"e"),
+            escrows_by_taker: SecondaryIndex::new(b"t"),
+            resolver_registry: ResolverRegistry::new(b"w", env::current_account_id()),
+            near_balances: LookupMap::new(b"n"),
+            revealed_secrets: SecretRegistry::new(b"x"),
+            near_wrapper: NearWrapper::default(),
+            legacy_resolvers_orders: LookupMap::new(LEGACY_RESOLVERS_ORDERS_PREFIX)
         }
     }
 }
@@ -33,124 +87,129 @@ impl Default for EscrowDst {
 #[near_bindgen]
 impl EscrowDst {
     // This function is called when a fungible token is transferred to the contract
-    // It expects a hex-encoded string representing the immutable order data
+    // It expects a hex-encoded DstEscrowParams: the immutables and the src cancellation timestamp
+    // The dst safety deposit is reserved from the sender's NEAR balance (see near_balances.rs)
+    // A NEAR taking token can be funded in wNEAR, it is unwrapped when paid to the maker
+    // Multi tokens are funded the same way through mt_on_transfer (see assets.rs)
     pub fn ft_on_transfer(
         &mut self, 
         sender_id: AccountId, 
         amount: NearToken, 
         msg: String
     ) -> PromiseOrValue<NearToken> {
-        // Validate hex string
-        let bytes_hex = hex::decode(msg).expect("Invalid hex string provided");
-
-        let immutables = Immutables::try_from_slice(&bytes_hex)
-            .expect("Invalid immutable data");
-
-        // validate the amount of tokens and return if there are extra
-        require!(immutables.taking_amount <= amount, "Insufficient amount...");
-
-        // validate the sender
-        require!(sender_id == immutables.taker, "Invalid sender...");
-
-        // Check that the escrow cancellation will start not later than the cancellation time on the source chain.
-        require!(immutables.timelock.dst_cancellation < immutables.timelock.src_cancellation, "Invalid cancellation time...");
-     
-        let unused_tokens = amount.checked_sub(immutables.making_amount)
-            .expect("Overflow when calculating unused tokens");
-
-
-        // create order and refund unused amount
-        self.resolvers_orders.insert(immutables.hash(), ResolverOrder { immutables, safty_deposit: ZERO_NEAR});
-        
-        PromiseOrValue::Value(unused_tokens)
-    }
-
-    // This function is used by resolver to deposit the safty amount
-    // For now anyone can call it as long as they do it before dst_withdrawal
-    #[payable]
-    pub fn deposit_safty_amount(&mut self, immutables: Immutables) {
-        // safty amount should be deposited before dst withdrow
-        // if failed, resolver can only cancel it after dst_cancel
-        require!(shared_lib::utils::_only_before(immutables.timelock.dst_withdrawal), "Too late to deposit safty amount...");
-
-        let attached_deposit = env::attached_deposit();
-        require!(attached_deposit == immutables.dst_safty_deposit, "Invalid or no safty deposit...");
-
-        if let Some(value) = self.resolvers_orders.get_mut(&immutables.hash()) {
-            value.safty_deposit = attached_deposit;
-        }
+        let token = Asset::Ft(env::predecessor_account_id());
+        PromiseOrValue::Value(self.fund_transferred_escrow(sender_id, token, amount, msg))
     }
 
     /**
      * @dev The function works on the time intervals highlighted with capital letters:
-     * ---- contract deployed --/-- finality --/-- PRIVATE WITHDRAWAL --/-- PUBLIC WITHDRAWAL --/-- private cancellation ----
+     * ---- contract deployed --/-- finality --/-- PRIVATE WITHDRAWAL --/-- PUBLIC WITHDRAWAL --/--
+     * --/-- private cancellation --/-- public cancellation ----
      */
-    pub fn withdraw(&mut self, secret: String, immutables: Immutables) {
+    pub fn withdraw(&mut self, secret: Option<String>, immutables: Immutables) -> Promise {
         // only taker can call it
         require!(env::predecessor_account_id() == immutables.taker, "Only taker can withdraw...");
-        require!(shared_lib::utils::_only_after(immutables.timelock.dst_withdrawal));
-        require!(shared_lib::utils::_only_before(immutables.timelock.dst_cancellation));
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::DstWithdrawal));
+        require!(shared_lib::utils::_only_before(&timelock, Stage::DstCancellation));
 
-        // validate secret
-        require!(shared_lib::utils::validate_secret(secret, immutables.hashlock), "Invalid secret...");
+        // validate secret, a secret already revealed for the hashlock can be reused
+        let secret = self.reveal_secret(secret, &immutables, &timelock);
 
         // withdraw tokens
-        let taking_token = AccountId::from_str(&immutables.taking_token).expect("invalid token...");
-        let receiver_id = AccountId::from_str(&immutables.maker).expect("Invalid receiver account...");
-        self.safe_ft_transfer(
-            taking_token, 
-            receiver_id, 
-            immutables.taking_amount
-        );
-
-        // recover dst safty amount
-        Promise::new(env::predecessor_account_id()).transfer(immutables.dst_safty_deposit);
+        let receiver_id = AccountId::from_str(&immutables.receiver).expect("Invalid receiver account...");
+        let event = Self::withdrawn_event(&immutables, secret, receiver_id.clone());
+        self.settle_escrow(&immutables, EscrowStatus::Withdrawn, receiver_id, event)
     }
 
 
     /**
      * @dev The function works on the time intervals highlighted with capital letters:
-     * ---- contract deployed --/-- finality --/-- private withdrawal --/-- PUBLIC WITHDRAWAL --/-- private cancellation ----
+     * ---- contract deployed --/-- finality --/-- private withdrawal --/-- PUBLIC WITHDRAWAL --/--
+     * --/-- private cancellation --/-- public cancellation ----
      */
-    pub fn public_withdraw(&mut self,secret: String, immutables: Immutables) {
+    pub fn public_withdraw(&mut self,secret: Option<String>, immutables: Immutables) -> Promise {
         // anyone can call it
-        require!(shared_lib::utils::_only_after(immutables.timelock.dst_public_withdrawal));
-        require!(shared_lib::utils::_only_before(immutables.timelock.dst_cancellation));
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::DstPublicWithdrawal));
+        require!(shared_lib::utils::_only_before(&timelock, Stage::DstCancellation));
         
-        // validate secret
-        require!(shared_lib::utils::validate_secret(secret, immutables.hashlock), "Invalid secret...");
+        // validate secret, a secret already revealed for the hashlock can be reused
+        let secret = self.reveal_secret(secret, &immutables, &timelock);
         
         // withdraw tokens
-        let taking_token = AccountId::from_str(&immutables.taking_token).expect("invalid token...");
-        let receiver_id = AccountId::from_str(&immutables.maker).expect("Invalid receiver account...");
-        self.safe_ft_transfer(
-            taking_token, 
-            receiver_id, 
-            immutables.taking_amount
-        );
-
-        // recover dst safty amount
-        Promise::new(env::predecessor_account_id()).transfer(immutables.dst_safty_deposit);
+        let receiver_id = AccountId::from_str(&immutables.receiver).expect("Invalid receiver account...");
+        let event = Self::withdrawn_event(&immutables, secret, receiver_id.clone());
+        self.settle_escrow(&immutables, EscrowStatus::Withdrawn, receiver_id, event)
     }
 
 
     /**
      * @dev The function works on the time interval highlighted with capital letters:
-     * ---- contract deployed --/-- finality --/-- private withdrawal --/-- public withdrawal --/-- PRIVATE CANCELLATION ----
+     * ---- contract deployed --/-- finality --/-- private withdrawal --/-- public withdrawal --/--
+     * --/-- PRIVATE CANCELLATION --/-- PUBLIC CANCELLATION ----
      */
-    pub fn cancel(&mut self,  immutables: Immutables) {
+    pub fn cancel(&mut self,  immutables: Immutables) -> Promise {
         // only taker can call it
         require!(env::predecessor_account_id() == immutables.taker, "Only taker can cancel");
-        require!(shared_lib::utils::_only_after(immutables.timelock.dst_cancellation));
-
-        let taking_token = AccountId::from_str(&immutables.taking_token)
-            .expect("invalid token...");
-        ext_ft::ext(taking_token)
-            .with_static_gas(Gas::from_tgas(30))
-            .ft_transfer(env::predecessor_account_id(), immutables.taking_amount, Some("Order Cancelled".to_string()));
-    
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::DstCancellation));
+
+        // send resolver's tokens back
+        let event = EscrowEvent::Cancelled(Self::cancelled_event(&immutables, env::predecessor_account_id()));
+        self.settle_escrow(&immutables, EscrowStatus::Cancelled, env::predecessor_account_id(), event)
+    }
+
+    /**
+     * @dev The function works on the time interval highlighted with capital letters:
+     * ---- contract deployed --/-- finality --/-- private withdrawal --/-- public withdrawal --/--
+     * --/-- private cancellation --/-- PUBLIC CANCELLATION ----
+     */
+    pub fn public_cancel(&mut self, immutables: Immutables) -> Promise {
+        // anyone can call it, the safty deposit is the incentive
+
+        // only after Timelock.dst_public_cancellation
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::DstPublicCancellation));
+
+        // send resolver's tokens back
+        let taker = AccountId::from_str(&immutables.taker).expect("Invalid receiver account...");
+        let event = EscrowEvent::PublicCancelled(Self::cancelled_event(&immutables, taker.clone()));
+        self.settle_escrow(&immutables, EscrowStatus::Cancelled, taker, event)
+    }
+
+    // Finalizes a withdrawal or cancellation once the token transfer has settled.
+    // On success the escrow is removed, its recorded safety deposit goes to whoever settled it
+    // and the settlement event is emitted; on failure the escrow is re-activated so it can be
+    // settled again, holding whatever asset the failed payout was converted to.
+    #[private]
+    pub fn on_settlement_resolved(
+        &mut self,
+        immutables_hash: String,
+        safty_deposit_receiver: AccountId,
+        event: EscrowEvent,
+        #[callback_result] transfer_result: Result<(), PromiseError>
+    ) -> bool {
+        if transfer_result.is_err() {
+            log!("Settlement of escrow {} failed, escrow is active again", immutables_hash);
+            if let Some(order) = self.resolvers_orders.get_mut(&immutables_hash) {
+                order.status = EscrowStatus::Active;
+            }
+            return false;
+        }
+
+        let order = self.resolvers_orders.remove(&immutables_hash)
+            .expect("Escrow doesn't exist...");
+        let taker = AccountId::from_str(&order.immutables.taker).expect("Invalid taker account...");
+        self.escrows_by_taker.remove(&taker, &immutables_hash);
+        event.emit();
+
         // recover dst safty amount
-        Promise::new(env::predecessor_account_id()).transfer(immutables.dst_safty_deposit);
+        if order.safty_deposit > ZERO_NEAR {
+            Promise::new(safty_deposit_receiver).transfer(order.safty_deposit).detach();
+        }
+
+        true
     }
 
 
@@ -158,4 +217,316 @@ impl EscrowDst {
     pub fn check_order(&self, immutables: Immutables) -> bool {
         self.resolvers_orders.contains_key(&immutables.hash())
     }
-}
\ No newline at end of file
+}
+
+
+// block of internal functions
+impl EscrowDst {
+    // Funds an escrow with the hex-encoded borsh DstEscrowParams of a token transfer (ft_on_transfer,
+    // mt_on_transfer), returns the amount to refund to the sender
+    fn fund_transferred_escrow(&mut self, sender_id: AccountId, token: Asset, amount: NearToken, msg: String) -> NearToken {
+        // Validate hex string
+        let bytes_hex = hex::decode(msg).expect("Invalid hex string provided");
+
+        let DstEscrowParams { immutables, src_cancellation_timestamp } = DstEscrowParams::try_from_slice(&bytes_hex)
+            .expect("Invalid immutable data");
+
+        // the escrow locks exactly taking_amount of the taking token, extra is returned
+        require!(
+            immutables.taking_token == token || (immutables.taking_token == Asset::Near && self.is_wrapped_near(&token)),
+            "Invalid token..."
+        );
+        let unused_tokens = amount.checked_sub(immutables.taking_amount)
+            .expect("Insufficient amount...");
+        let immutables_hash = self.validate_new_escrow(&immutables, &sender_id, src_cancellation_timestamp);
+
+        // reserve the safety deposit from the resolver's NEAR balance, no deposit no escrow
+        if !self.reserve_near(&sender_id, immutables.dst_safty_deposit) {
+            log!("Insufficient NEAR balance for the safety deposit, refunding {}", amount);
+            return amount;
+        }
+
+        // create order and refund unused amount
+        self.store_escrow(immutables, immutables_hash, sender_id, token);
+
+        unused_tokens
+    }
+
+    // Checks shared by every way of funding an escrow, returns the escrow's immutables hash
+    fn validate_new_escrow(&self, immutables: &Immutables, taker: &AccountId, src_cancellation_timestamp: u32) -> String {
+        // validate the sender
+        require!(*taker == immutables.taker, "Invalid sender...");
+        self.resolver_registry.assert_active(taker);
+        require!(immutables.taking_amount > ZERO_NEAR, "Taking amount must be greater than zero...");
+
+        // validate formats before storing anything
+        require!(shared_lib::utils::is_valid_hashlock(&immutables.hashlock), "Invalid hashlock format...");
+        require!(AccountId::from_str(&immutables.maker).is_ok(), "Invalid maker account...");
+        require!(AccountId::from_str(&immutables.receiver).is_ok(), "Invalid receiver account...");
+
+        // Check phase ordering, and that this escrow, anchored now, starts cancellation
+        // no later than the already deployed src escrow
+        let timelock = &immutables.timelock;
+        if let Err(err) = timelock.verify().and_then(|_| timelock.verify_dst_cancellation(TimeLock::now(), src_cancellation_timestamp)) {
+            env::panic_str(&err.to_string());
+        }
+
+        // an escrow is funded once, and never for a secret that is already public
+        let immutables_hash = immutables.hash();
+        require!(!self.resolvers_orders.contains_key(&immutables_hash), "Escrow already exists...");
+        require!(self.revealed_secrets.get(&immutables.hashlock).is_none(), "Secret of the hashlock is already revealed...");
+        immutables_hash
+    }
+
+    // Stores a funded escrow, its timelocks count from now on
+    fn store_escrow(&mut self, mut immutables: Immutables, immutables_hash: String, taker: AccountId, held_asset: Asset) {
+        immutables.timelock.set_deployed_at();
+        let safty_deposit = immutables.dst_safty_deposit;
+
+        EscrowEvent::DstEscrowCreated(
+            EscrowCreatedEvent::new(immutables_hash.clone(), &immutables, safty_deposit)
+        ).emit();
+        self.escrows_by_taker.insert(&taker, immutables_hash.clone());
+        EscrowEvent::SafetyDepositAdded(SafetyDepositEvent {
+            immutables_hash: immutables_hash.clone(),
+            account_id: taker,
+            amount: safty_deposit
+        }).emit();
+
+        self.resolvers_orders.insert(immutables_hash, ResolverOrder {
+            immutables,
+            safty_deposit,
+            status: EscrowStatus::Active,
+            held_asset
+        });
+    }
+
+    fn withdrawn_event(immutables: &Immutables, secret: String, receiver: AccountId) -> EscrowEvent {
+        EscrowEvent::Withdrawn(WithdrawnEvent {
+            immutables_hash: immutables.hash(),
+            hashlock: immutables.hashlock.clone(),
+            secret,
+            receiver,
+            token: immutables.taking_token.clone(),
+            amount: immutables.taking_amount,
+            caller: env::predecessor_account_id()
+        })
+    }
+
+    fn cancelled_event(immutables: &Immutables, receiver: AccountId) -> CancelledEvent {
+        CancelledEvent {
+            immutables_hash: immutables.hash(),
+            receiver,
+            token: immutables.taking_token.clone(),
+            amount: immutables.taking_amount,
+            caller: env::predecessor_account_id()
+        }
+    }
+
+    // Timelock of a funded escrow, anchored at the time it was created
+    fn escrow_timelock(&self, immutables: &Immutables) -> TimeLock {
+        self.resolvers_orders.get(&immutables.hash())
+            .expect("Escrow doesn't exist...")
+            .immutables.timelock.clone()
+    }
+
+    // Moves an active escrow into its final status and pays out its taking amount
+    // (native NEAR or fungible token, see transfer_asset). Withdrawals pay the receiver the
+    // taking token, wrapping or unwrapping the held NEAR; cancellations refund it as held.
+    // Both assets were checked against the wNEAR contract when the escrow was funded, so the
+    // conversion goes through that contract even if the owner has changed it since.
+    // A conversion that succeeded before a failed payout is kept as the new held asset.
+    // The entry stays in storage until on_settlement_resolved sees the transfer result,
+    // which blocks any second settlement of the same escrow in the meantime.
+    fn settle_escrow(&mut self, immutables: &Immutables, status: EscrowStatus, receiver_id: AccountId, event: EscrowEvent) -> Promise {
+        let immutables_hash = immutables.hash();
+        let order = self.resolvers_orders.get_mut(&immutables_hash)
+            .expect("Escrow doesn't exist...");
+        require!(order.status == EscrowStatus::Active, "Escrow already settled...");
+        let payout_asset = if status == EscrowStatus::Withdrawn { immutables.taking_token.clone() } else { order.held_asset.clone() };
+        let held_asset = order.held_asset.clone();
+        order.status = status;
+
+        NearWrapper::transfer(self, &held_asset, &payout_asset, immutables_hash.clone(), receiver_id, immutables.taking_amount)
+            .then(
+                ext_escrow::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_SETTLEMENT)
+                    .on_settlement_resolved(immutables_hash, env::predecessor_account_id(), event)
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{mock::MockAction, test_utils::{get_created_receipts, VMContextBuilder}, testing_env};
+
+    use super::*;
+
+    const ESCROW: &str = "escrow.near";
+    const RESOLVER: &str = "resolver.near";
+    const WRAP: &str = "wrap.near";
+
+    fn set_caller(caller: &str) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(ESCROW.parse().unwrap())
+            .predecessor_account_id(caller.parse().unwrap())
+            .build());
+    }
+
+    fn wnear() -> Asset {
+        Asset::Ft(WRAP.parse().unwrap())
+    }
+
+    fn immutables(taking_token: Asset) -> Immutables {
+        Immutables {
+            salt: "salt".to_string(),
+            order_root_hash: "0x".to_string() + &"11".repeat(32),
+            hashlock: "0x".to_string() + &"11".repeat(32),
+            making_token: Asset::Ft("token.near".parse().unwrap()),
+            taking_token,
+            making_amount: NearToken::from_near(1),
+            taking_amount: NearToken::from_near(2),
+            src_safty_deposit: ZERO_NEAR,
+            dst_safty_deposit: ZERO_NEAR,
+            timelock: TimeLock {
+                deployed_at: 0,
+                src_withdrawal: 60,
+                src_public_withdrawal: 120,
+                src_cancellation: 240,
+                src_public_cancellation: 300,
+                dst_withdrawal: 60,
+                dst_public_withdrawal: 120,
+                dst_cancellation: 180,
+                dst_public_cancellation: 240
+            },
+            maker: "maker.near".to_string(),
+            taker: RESOLVER.to_string(),
+            receiver: "maker.near".to_string()
+        }
+    }
+
+    // contract holding an escrow of `immutables` funded in `held_asset`
+    fn contract(immutables: &Immutables, held_asset: Asset) -> EscrowDst {
+        set_caller(RESOLVER);
+        let mut contract = EscrowDst::default();
+        contract.store_escrow(immutables.clone(), immutables.hash(), RESOLVER.parse().unwrap(), held_asset);
+        contract
+    }
+
+    fn called_methods() -> Vec<String> {
+        get_created_receipts().into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                MockAction::FunctionCallWeight { method_name, .. } => Some(String::from_utf8(method_name).unwrap()),
+                _ => None
+            })
+            .collect()
+    }
+
+    fn withdraw(contract: &mut EscrowDst, immutables: &Immutables) {
+        let event = EscrowDst::withdrawn_event(immutables, "secret".to_string(), "maker.near".parse().unwrap());
+        contract.settle_escrow(immutables, EscrowStatus::Withdrawn, "maker.near".parse().unwrap(), event).detach();
+    }
+
+    #[test]
+    fn failed_payout_keeps_the_converted_asset() {
+        let immutables = immutables(Asset::Near);
+        let immutables_hash = immutables.hash();
+        let mut contract = contract(&immutables, wnear());
+
+        // wNEAR is unwrapped, then paying out the NEAR fails
+        withdraw(&mut contract, &immutables);
+        set_caller(ESCROW);
+        contract.on_near_unwrapped(immutables_hash.clone(), WRAP.parse().unwrap(), "maker.near".parse().unwrap(), immutables.taking_amount, Ok(())).detach();
+        let event = EscrowDst::withdrawn_event(&immutables, "secret".to_string(), "maker.near".parse().unwrap());
+        assert!(!contract.on_settlement_resolved(immutables_hash.clone(), RESOLVER.parse().unwrap(), event, Err(PromiseError::Failed)));
+
+        let order = contract.resolvers_orders.get(&immutables_hash).unwrap();
+        assert_eq!(order.status, EscrowStatus::Active);
+        assert_eq!(order.held_asset, Asset::Near);
+
+        // the retry pays out the NEAR the escrow now holds, nothing is unwrapped twice
+        set_caller(ESCROW);
+        withdraw(&mut contract, &immutables);
+        assert!(!called_methods().contains(&"near_withdraw".to_string()));
+        assert!(get_created_receipts().iter().flat_map(|receipt| &receipt.actions).any(|action| matches!(action, MockAction::Transfer { .. })));
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow already settled...")]
+    fn escrow_settles_once() {
+        let immutables = immutables(Asset::Near);
+        let mut contract = contract(&immutables, Asset::Near);
+        withdraw(&mut contract, &immutables);
+        withdraw(&mut contract, &immutables);
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow doesn't exist...")]
+    fn missing_escrow_can_not_be_settled() {
+        let immutables = immutables(Asset::Near);
+        let mut contract = contract(&immutables, Asset::Near);
+        let mut other = immutables.clone();
+        other.salt = "other salt".to_string();
+        withdraw(&mut contract, &other);
+    }
+
+    #[test]
+    fn failed_settlement_reactivates_the_escrow() {
+        let immutables = immutables(Asset::Near);
+        let immutables_hash = immutables.hash();
+        let mut contract = contract(&immutables, Asset::Near);
+        withdraw(&mut contract, &immutables);
+        assert_eq!(contract.resolvers_orders.get(&immutables_hash).unwrap().status, EscrowStatus::Withdrawn);
+
+        set_caller(ESCROW);
+        let event = EscrowDst::withdrawn_event(&immutables, "secret".to_string(), "maker.near".parse().unwrap());
+        assert!(!contract.on_settlement_resolved(immutables_hash.clone(), RESOLVER.parse().unwrap(), event, Err(PromiseError::Failed)));
+        assert_eq!(contract.resolvers_orders.get(&immutables_hash).unwrap().status, EscrowStatus::Active);
+
+        // settled again, this time for good
+        withdraw(&mut contract, &immutables);
+        let event = EscrowDst::withdrawn_event(&immutables, "secret".to_string(), "maker.near".parse().unwrap());
+        assert!(contract.on_settlement_resolved(immutables_hash.clone(), RESOLVER.parse().unwrap(), event, Ok(())));
+        assert!(contract.resolvers_orders.get(&immutables_hash).is_none());
+        assert!(contract.escrows_by_taker.iter(&RESOLVER.parse().unwrap()).next().is_none());
+    }
+
+    // contract where RESOLVER may fund escrows
+    fn registered_contract() -> EscrowDst {
+        set_caller(ESCROW);
+        let mut contract = EscrowDst::default();
+        contract.resolver_registry.add(RESOLVER.parse().unwrap());
+        set_caller(RESOLVER);
+        contract
+    }
+
+    #[test]
+    fn new_escrow_passes_validation() {
+        let immutables = immutables(Asset::Near);
+        let contract = registered_contract();
+        assert_eq!(contract.validate_new_escrow(&immutables, &RESOLVER.parse().unwrap(), 1_000), immutables.hash());
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow already exists...")]
+    fn escrow_can_not_be_funded_twice() {
+        let immutables = immutables(Asset::Near);
+        let mut contract = registered_contract();
+        contract.store_escrow(immutables.clone(), immutables.hash(), RESOLVER.parse().unwrap(), Asset::Near);
+        contract.validate_new_escrow(&immutables, &RESOLVER.parse().unwrap(), 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Secret of the hashlock is already revealed...")]
+    fn revealed_hashlock_can_not_be_reused() {
+        let immutables = immutables(Asset::Near);
+        let mut contract = registered_contract();
+        contract.revealed_secrets.record(&immutables.hashlock, "settled escrow".to_string(), "secret".to_string(), 0);
+
+        // another escrow (other salt, so another hash) on the same hashlock
+        let mut replay = immutables.clone();
+        replay.salt = "other salt".to_string();
+        contract.validate_new_escrow(&replay, &RESOLVER.parse().unwrap(), 1_000);
+    }
+}
@@ -0,0 +1,216 @@
+use shared_lib::legacy::LegacyImmutables;
+
+use crate::*;
+
+// Prefix of the first deployed layout, its entries stay readable there (see rescue_legacy_escrow)
+pub(crate) const LEGACY_RESOLVERS_ORDERS_PREFIX: &[u8] = b"r";
+
+const GAS_FOR_RESOLVE_LEGACY_RESCUE: Gas = Gas::from_tgas(10);
+
+#[ext_contract(ext_legacy)]
+trait _LegacyCallbacks {
+    fn on_legacy_escrow_rescued(&mut self, immutables_hash: String, order: LegacyResolverOrder) -> bool;
+}
+
+// First deployed layout: a single LookupMap, whose borsh encoding is just its prefix
+#[derive(BorshDeserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+struct LegacyEscrowDst {
+    resolvers_orders: Vec<u8>
+}
+
+// Escrow of the first deployed layout, never removed once settled back then
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct LegacyResolverOrder {
+    pub immutables: LegacyImmutables,
+    pub safty_deposit: NearToken
+}
+
+#[near_bindgen]
+impl EscrowDst {
+    // Upgrades the first deployed layout to the current one (deploy the new code, then call this)
+    // Escrows of the old layout can't be enumerated and miss the receiver, so they stay where
+    // they are, readable through legacy_resolvers_orders and paid out with rescue_legacy_escrow.
+    // The current map uses another prefix, so it never aliases entries of the old layout.
+    // Panics on any other state, which also keeps the migration from running twice.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let legacy: LegacyEscrowDst = env::state_read().expect("Contract state doesn't exist...");
+        require!(legacy.resolvers_orders == LEGACY_RESOLVERS_ORDERS_PREFIX, "Unknown contract state layout...");
+
+        log!("Migrated escrow state to the current layout");
+        Self::default()
+    }
+
+    pub fn get_legacy_escrow(&self, immutables_hash: String) -> Option<LegacyResolverOrder> {
+        self.legacy_resolvers_orders.get(&immutables_hash).cloned()
+    }
+
+    // Owner pays out an escrow funded before the upgrade: its taking amount to `receiver_id`,
+    // the maker or the taker depending on how the swap went, and the safety deposit the taker
+    // actually deposited back to it. The first layout kept settled escrows around, so the owner
+    // drops those by passing no receiver.
+    pub fn rescue_legacy_escrow(&mut self, immutables_hash: String, receiver_id: Option<AccountId>) -> PromiseOrValue<bool> {
+        self.resolver_registry.assert_owner();
+        let order = self.legacy_resolvers_orders.remove(&immutables_hash)
+            .expect("Escrow doesn't exist...");
+
+        let Some(receiver_id) = receiver_id else {
+            log!("Dropped settled legacy escrow {}", immutables_hash);
+            return PromiseOrValue::Value(true);
+        };
+        order.immutables.assert_party(&receiver_id);
+
+        let token = AccountId::from_str(&order.immutables.taking_token).expect("Invalid token...");
+        let amount = order.immutables.taking_amount;
+        PromiseOrValue::Promise(
+            self.safe_ft_transfer(token, receiver_id, amount)
+                .then(
+                    ext_legacy::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_LEGACY_RESCUE)
+                        .on_legacy_escrow_rescued(immutables_hash, order)
+                )
+        )
+    }
+
+    // Returns the safety deposit once the payout went through, puts the escrow back otherwise
+    #[private]
+    pub fn on_legacy_escrow_rescued(
+        &mut self,
+        immutables_hash: String,
+        order: LegacyResolverOrder,
+        #[callback_result] transfer_result: Result<(), PromiseError>
+    ) -> bool {
+        if transfer_result.is_err() {
+            log!("Rescue of legacy escrow {} failed", immutables_hash);
+            self.legacy_resolvers_orders.insert(immutables_hash, order);
+            return false;
+        }
+
+        if order.safty_deposit > ZERO_NEAR {
+            Promise::new(order.immutables.taker()).transfer(order.safty_deposit).detach();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+    use shared_lib::legacy::LegacyTimeLock;
+
+    use super::*;
+
+    #[derive(BorshSerialize)]
+    #[borsh(crate = "near_sdk::borsh")]
+    struct LegacyState {
+        resolvers_orders: Vec<u8>
+    }
+
+    fn set_escrow() {
+        set_caller("escrow.near");
+    }
+
+    fn set_caller(caller: &str) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("escrow.near".parse().unwrap())
+            .predecessor_account_id(caller.parse().unwrap())
+            .build());
+    }
+
+    fn legacy_escrow() -> LegacyResolverOrder {
+        LegacyResolverOrder {
+            immutables: LegacyImmutables {
+                salt: "salt".to_string(),
+                order_root_hash: "root".to_string(),
+                hashlock: "root".to_string(),
+                making_token: "0xtoken".to_string(),
+                taking_token: "token.near".to_string(),
+                making_amount: NearToken::from_yoctonear(400),
+                taking_amount: NearToken::from_yoctonear(400),
+                src_safty_deposit: NearToken::from_yoctonear(10),
+                dst_safty_deposit: NearToken::from_yoctonear(10),
+                timelock: LegacyTimeLock {
+                    src_withdrawal: 1,
+                    src_public_withdrawal: 2,
+                    src_cancellation: 3,
+                    src_public_cancellation: 4,
+                    dst_withdrawal: 1,
+                    dst_public_withdrawal: 2,
+                    dst_cancellation: 3
+                },
+                maker: "maker.near".to_string(),
+                taker: "resolver.near".to_string()
+            },
+            safty_deposit: NearToken::from_yoctonear(10)
+        }
+    }
+
+    // legacy state with one escrow, written as the first layout did
+    fn migrated_contract() -> EscrowDst {
+        set_escrow();
+        env::state_write(&LegacyState { resolvers_orders: b"r".to_vec() });
+        let mut escrows = LookupMap::new(LEGACY_RESOLVERS_ORDERS_PREFIX);
+        escrows.insert("escrow".to_string(), legacy_escrow());
+        escrows.flush();
+
+        EscrowDst::migrate()
+    }
+
+    #[test]
+    fn migrates_the_first_layout() {
+        set_escrow();
+        env::state_write(&LegacyState { resolvers_orders: b"r".to_vec() });
+
+        let contract = EscrowDst::migrate();
+        assert_eq!(contract.resolver_registry.owner().as_str(), "escrow.near");
+        assert_eq!(contract.resolvers_orders.len(), 0);
+    }
+
+    #[test]
+    fn owner_pays_out_or_drops_legacy_escrows() {
+        let mut contract = migrated_contract();
+        assert_eq!(contract.get_legacy_escrow("escrow".to_string()).unwrap().immutables.maker, "maker.near");
+        assert!(matches!(contract.rescue_legacy_escrow("escrow".to_string(), Some("maker.near".parse().unwrap())), PromiseOrValue::Promise(_)));
+        assert!(contract.get_legacy_escrow("escrow".to_string()).is_none());
+
+        // a failed payout puts the escrow back, a settled one is just dropped
+        assert!(!contract.on_legacy_escrow_rescued("escrow".to_string(), legacy_escrow(), Err(PromiseError::Failed)));
+        assert!(matches!(contract.rescue_legacy_escrow("escrow".to_string(), None), PromiseOrValue::Value(true)));
+        assert!(contract.get_legacy_escrow("escrow".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Receiver must be the maker or the taker...")]
+    fn legacy_escrows_only_pay_their_parties() {
+        let mut contract = migrated_contract();
+        contract.rescue_legacy_escrow("escrow".to_string(), Some("owner.near".parse().unwrap())).detach();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can manage resolvers...")]
+    fn only_owner_rescues_an_escrow() {
+        let mut contract = migrated_contract();
+        set_caller("resolver.near");
+        contract.rescue_legacy_escrow("escrow".to_string(), None).detach();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown contract state layout...")]
+    fn rejects_other_prefixes() {
+        set_escrow();
+        env::state_write(&LegacyState { resolvers_orders: b"e".to_vec() });
+        EscrowDst::migrate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot deserialize the contract state.")]
+    fn can_not_migrate_twice() {
+        set_escrow();
+        env::state_write(&EscrowDst::default());
+        EscrowDst::migrate();
+    }
+}
@@ -0,0 +1,45 @@
+use crate::*;
+
+// Per-resolver NEAR ledger the dst safety deposits are reserved from.
+// FT transfers can't carry NEAR, so resolvers pre-fund it once and ft_on_transfer
+// takes dst_safty_deposit out of it atomically with the escrow creation.
+#[near_bindgen]
+impl EscrowDst {
+    #[payable]
+    pub fn deposit_near(&mut self) {
+        let amount = env::attached_deposit();
+        require!(amount > ZERO_NEAR, "Nothing to deposit...");
+
+        let balance = self.near_balances.entry(env::predecessor_account_id()).or_insert(ZERO_NEAR);
+        *balance = balance.checked_add(amount).expect("Overflow when depositing NEAR");
+    }
+
+    pub fn withdraw_near(&mut self, amount: NearToken) -> Promise {
+        let account_id = env::predecessor_account_id();
+        require!(amount > ZERO_NEAR, "Amount must be greater than zero...");
+        require!(self.reserve_near(&account_id, amount), "Insufficient NEAR balance...");
+
+        Promise::new(account_id).transfer(amount)
+    }
+
+    pub fn get_near_balance(&self, account_id: AccountId) -> NearToken {
+        self.near_balances.get(&account_id).copied().unwrap_or(ZERO_NEAR)
+    }
+}
+
+impl EscrowDst {
+    // Takes `amount` out of the account's balance, false if it doesn't have enough
+    pub(crate) fn reserve_near(&mut self, account_id: &AccountId, amount: NearToken) -> bool {
+        let balance = self.get_near_balance(account_id.clone());
+        let Some(remaining) = balance.checked_sub(amount) else {
+            return false;
+        };
+
+        if remaining == ZERO_NEAR {
+            self.near_balances.remove(account_id);
+        } else {
+            self.near_balances.insert(account_id.clone(), remaining);
+        }
+        true
+    }
+}
@@ -0,0 +1,35 @@
+use shared_lib::resolver_registry::ResolverInfo;
+
+use crate::*;
+
+// Resolvers allowed to fund destination escrows, managed by the owner (see shared_lib::resolver_registry)
+#[near_bindgen]
+impl EscrowDst {
+    pub fn add_resolver(&mut self, account_id: AccountId) {
+        self.resolver_registry.add(account_id);
+    }
+
+    pub fn remove_resolver(&mut self, account_id: AccountId) {
+        self.resolver_registry.remove(&account_id);
+    }
+
+    pub fn set_resolver_enabled(&mut self, account_id: AccountId, enabled: bool) {
+        self.resolver_registry.set_enabled(&account_id, enabled);
+    }
+
+    pub fn set_owner(&mut self, owner: AccountId) {
+        self.resolver_registry.set_owner(owner);
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.resolver_registry.owner().clone()
+    }
+
+    pub fn is_resolver(&self, account_id: AccountId) -> bool {
+        self.resolver_registry.is_active(&account_id)
+    }
+
+    pub fn get_resolvers(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<ResolverInfo> {
+        self.resolver_registry.list(from_index, limit)
+    }
+}
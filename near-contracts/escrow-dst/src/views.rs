@@ -0,0 +1,36 @@
+use shared_lib::{escrow_status::EscrowStage, utils::page};
+
+use crate::*;
+
+// A funded escrow together with where it is on its timeline
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EscrowView {
+    pub immutables_hash: String,
+    pub order: ResolverOrder,
+    pub stage: EscrowStage
+}
+
+#[near_bindgen]
+impl EscrowDst {
+    pub fn get_escrow(&self, immutables_hash: String) -> Option<EscrowView> {
+        self.resolvers_orders.get(&immutables_hash)
+            .map(|order| Self::escrow_view(&immutables_hash, order))
+    }
+
+    pub fn get_escrows_by_taker(&self, taker: AccountId, from_index: Option<u32>, limit: Option<u32>) -> Vec<EscrowView> {
+        page(self.escrows_by_taker.iter(&taker), from_index, limit)
+            .filter_map(|immutables_hash| self.get_escrow(immutables_hash.clone()))
+            .collect()
+    }
+}
+
+impl EscrowDst {
+    fn escrow_view(immutables_hash: &str, order: &ResolverOrder) -> EscrowView {
+        EscrowView {
+            immutables_hash: immutables_hash.to_string(),
+            stage: EscrowStage::dst(&order.immutables.timelock, &order.status),
+            order: order.clone()
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use shared_lib::{assets::Asset, near_wrapper::{NearPayouts, NearWrapper}};
+
+use crate::*;
+
+// Escrows funded in wNEAR for a maker asking for NEAR (or the other way around) convert when
+// the maker withdraws. The conversion itself lives in shared_lib::near_wrapper.
+#[near_bindgen]
+impl EscrowDst {
+    // Owner sets the wNEAR contract resolvers may fund native NEAR escrows with.
+    // Changing it doesn't affect funded escrows, they convert through the token they hold.
+    pub fn set_wrap_account(&mut self, wrap_account: Option<AccountId>) {
+        self.resolver_registry.assert_owner();
+        self.near_wrapper.set_wrap_account(wrap_account);
+    }
+
+    pub fn get_wrap_account(&self) -> Option<AccountId> {
+        self.near_wrapper.wrap_account().cloned()
+    }
+
+    // Maker withdrawal of a wNEAR escrow funded with native NEAR, see NearWrapper::resolve_wrap
+    #[private]
+    pub fn on_near_wrapped(
+        &mut self,
+        settlement_id: String,
+        wrap_account: AccountId,
+        receiver_id: AccountId,
+        amount: NearToken,
+        #[callback_result] wrap_result: Result<(), PromiseError>
+    ) -> Promise {
+        NearWrapper::resolve_wrap(self, settlement_id, wrap_account, receiver_id, amount, wrap_result)
+    }
+
+    // Maker withdrawal of a NEAR escrow funded with wNEAR, see NearWrapper::resolve_unwrap
+    #[private]
+    pub fn on_near_unwrapped(
+        &mut self,
+        settlement_id: String,
+        wrap_account: AccountId,
+        receiver_id: AccountId,
+        amount: NearToken,
+        #[callback_result] unwrap_result: Result<(), PromiseError>
+    ) -> Promise {
+        NearWrapper::resolve_unwrap(self, settlement_id, wrap_account, receiver_id, amount, unwrap_result)
+    }
+}
+
+impl EscrowDst {
+    pub(crate) fn is_wrapped_near(&self, asset: &Asset) -> bool {
+        self.near_wrapper.is_wrapped_near(asset)
+    }
+}
+
+impl NearPayouts for EscrowDst {
+    fn pay_out(&mut self, asset: &Asset, receiver_id: AccountId, amount: NearToken) -> Promise {
+        self.transfer_asset(asset, receiver_id, amount)
+    }
+
+    // The escrow now holds the converted asset, a re-activated escrow pays it out from there
+    fn record_conversion(&mut self, settlement_id: &str, asset: Asset) {
+        if let Some(order) = self.resolvers_orders.get_mut(settlement_id) {
+            order.held_asset = asset;
+        }
+    }
+}
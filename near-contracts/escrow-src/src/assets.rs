@@ -0,0 +1,91 @@
+use shared_lib::{assets::Asset, multi_tokens::ext_mt, non_fungible_tokens::ext_nft};
+
+use crate::*;
+
+const GAS_FOR_MT_TRANSFER: Gas = Gas::from_tgas(20);
+const GAS_FOR_NFT_TRANSFER: Gas = Gas::from_tgas(20);
+
+// An NFT is escrowed as an amount of 1
+pub const NFT_AMOUNT: NearToken = NearToken::from_yoctonear(1);
+
+#[near_bindgen]
+impl EscrowSrc {
+    // Places a maker order paid with the attached NEAR, the native counterpart of
+    // ft_on_transfer. Attached NEAR above total_amount is refunded.
+    #[payable]
+    pub fn create_native_order(&mut self, maker_order: MakerOrder) {
+        let amount = env::attached_deposit();
+
+        require!(maker_order.maker == env::predecessor_account_id(), "Maker order does not match the sender ID");
+        require!(maker_order.token == Asset::Near, "Invalid token");
+        require!(maker_order.total_amount > ZERO_NEAR, "Maker order total amount must be greater than zero");
+        require!(maker_order.total_amount <= amount, "Maker order total amount is greater than the attached amount");
+        self.validate_new_maker_order(&maker_order);
+
+        // Return unused NEAR if any
+        let unused_amount = amount.saturating_sub(maker_order.total_amount);
+        if unused_amount > ZERO_NEAR {
+            Promise::new(maker_order.maker.clone()).transfer(unused_amount).detach();
+        }
+
+        self.store_maker_order(maker_order);
+    }
+
+    // NEP-245 counterpart of ft_on_transfer: the msg is a hex-encoded MakerOrder whose token
+    // is the Asset::Mt of the single transferred token id. As for NFTs, the tokens must be
+    // sent by their owner, who is the maker.
+    pub fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<NearToken>,
+        msg: String
+    ) -> PromiseOrValue<Vec<NearToken>> {
+        require!(token_ids.len() == 1 && amounts.len() == 1, "Only one token id per transfer...");
+        require!(previous_owner_ids.len() == 1 && previous_owner_ids[0] == sender_id, "Tokens must be sent by their owner...");
+
+        let token = Asset::Mt { contract: env::predecessor_account_id(), token_id: token_ids[0].clone() };
+        let unused_amount = self.place_transferred_order(&sender_id, token, amounts[0], msg);
+
+        PromiseOrValue::Value(vec![unused_amount])
+    }
+
+    // NEP-171 counterpart of ft_on_transfer: the msg is a hex-encoded single-part MakerOrder
+    // selling the NFT, with token Asset::Nft of the sent token and total_amount 1 (yocto).
+    // Returns true when the NFT has to go back to its owner.
+    pub fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: String,
+        msg: String
+    ) -> PromiseOrValue<bool> {
+        require!(sender_id == previous_owner_id, "NFT must be sent by its owner...");
+
+        let token = Asset::Nft { contract: env::predecessor_account_id(), token_id };
+        let unused_amount = self.place_transferred_order(&sender_id, token, NFT_AMOUNT, msg);
+
+        PromiseOrValue::Value(unused_amount > ZERO_NEAR)
+    }
+}
+
+impl EscrowSrc {
+    // Pays out an escrowed asset, native NEAR with a plain transfer, fungible tokens
+    // through safe_ft_transfer, multi tokens with mt_transfer and NFTs with nft_transfer
+    pub(crate) fn transfer_asset(&mut self, asset: &Asset, receiver_id: AccountId, amount: NearToken) -> Promise {
+        match asset.clone() {
+            Asset::Near => Promise::new(receiver_id).transfer(amount),
+            Asset::Ft(token_contract) => self.safe_ft_transfer(token_contract, receiver_id, amount),
+            Asset::Mt { contract, token_id } => ext_mt::ext(contract)
+                .with_attached_deposit(NearToken::from_yoctonear(1)) // 1 yoctoNEAR for mt_transfer
+                .with_static_gas(GAS_FOR_MT_TRANSFER)
+                .mt_transfer(receiver_id, token_id, amount, None, None),
+            Asset::Nft { contract, token_id } => ext_nft::ext(contract)
+                .with_attached_deposit(NearToken::from_yoctonear(1)) // 1 yoctoNEAR for nft_transfer
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer(receiver_id, token_id, None, None),
+            Asset::Foreign(_) => env::panic_str("Tokens of another chain can't be paid out...")
+        }
+    }
+}
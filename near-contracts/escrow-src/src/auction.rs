@@ -0,0 +1,192 @@
+use std::iter;
+
+use crate::*;
+
+// Auction rates are bumps on top of MakerOrder.min_taking_amount,
+// RATE_BUMP_DENOMINATOR means the maker asks for twice the minimum
+pub const RATE_BUMP_DENOMINATOR: u32 = 10_000_000;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct AuctionPoint {
+    pub delay: u64,                 // time since auction start (nanoseconds)
+    pub rate: u32                   // rate bump reached at this point
+}
+
+// Decaying price curve of a maker order
+// The rate is start_rate until start_time, then moves linearly through `points`
+// to end_rate at start_time + duration and stays there
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct AuctionDetails {
+    pub start_time: u64,            // auction start timestamp (nanoseconds)
+    pub duration: u64,              // auction length (nanoseconds)
+    pub start_rate: u32,            // rate bump at auction start
+    pub end_rate: u32,              // rate bump at auction end
+    pub points: Vec<AuctionPoint>   // optional piecewise-linear points in between
+}
+
+impl AuctionDetails {
+    // points must sit strictly inside the auction, in increasing order
+    pub fn is_valid(&self) -> bool {
+        if self.duration == 0 {
+            return false;
+        }
+
+        let mut last_delay = 0;
+        for point in &self.points {
+            if point.delay <= last_delay || point.delay >= self.duration {
+                return false;
+            }
+            last_delay = point.delay;
+        }
+
+        true
+    }
+
+    pub fn rate_at(&self, timestamp: u64) -> u32 {
+        if timestamp <= self.start_time {
+            return self.start_rate;
+        }
+
+        let elapsed = timestamp - self.start_time;
+        if elapsed >= self.duration {
+            return self.end_rate;
+        }
+
+        let end = AuctionPoint { delay: self.duration, rate: self.end_rate };
+        let mut previous = AuctionPoint { delay: 0, rate: self.start_rate };
+        for point in self.points.iter().chain(iter::once(&end)) {
+            if elapsed < point.delay {
+                return Self::interpolate(&previous, point, elapsed);
+            }
+            previous = point.clone();
+        }
+
+        self.end_rate
+    }
+
+    // Linear rate between two points, the moving part is rounded down
+    // so a decaying auction never drops below the curve
+    fn interpolate(from: &AuctionPoint, to: &AuctionPoint, elapsed: u64) -> u32 {
+        let span = (to.delay - from.delay) as u128;
+        let progress = (elapsed - from.delay) as u128;
+
+        if to.rate >= from.rate {
+            let change = (to.rate - from.rate) as u128 * progress / span;
+            from.rate + change as u32
+        } else {
+            let change = (from.rate - to.rate) as u128 * progress / span;
+            from.rate - change as u32
+        }
+    }
+}
+
+#[near_bindgen]
+impl EscrowSrc {
+    // Current auction rate bump of an order, 0 for orders without an auction
+    pub fn get_auction_rate(&self, order_root_hash: String) -> u32 {
+        let maker_order = self.makers_orders.get(&order_root_hash)
+            .expect("Order doesn't exist...");
+
+        maker_order.auction.as_ref()
+            .map(|auction| auction.rate_at(env::block_timestamp()))
+            .unwrap_or(0)
+    }
+
+    // Least taking amount a fill of `making_amount` has to ask for right now
+    pub fn get_min_taking_amount(&self, order_root_hash: String, making_amount: NearToken) -> NearToken {
+        let maker_order = self.makers_orders.get(&order_root_hash)
+            .expect("Order doesn't exist...");
+
+        Self::min_taking_amount(
+            &Self::current_order_taking_amount(maker_order),
+            &maker_order.total_amount,
+            &making_amount
+        )
+    }
+}
+
+impl EscrowSrc {
+    // Taking amount the whole order asks for at the current block
+    pub(crate) fn current_order_taking_amount(maker_order: &MakerOrder) -> NearToken {
+        let rate = match &maker_order.auction {
+            Some(auction) => auction.rate_at(env::block_timestamp()),
+            None => return maker_order.min_taking_amount
+        };
+
+        let taking_amount = shared_lib::utils::mul_div(
+            maker_order.min_taking_amount.as_yoctonear(),
+            RATE_BUMP_DENOMINATOR as u128 + rate as u128,
+            RATE_BUMP_DENOMINATOR as u128,
+            true
+        ).expect("Overflow when calculating auction taking amount");
+
+        NearToken::from_yoctonear(taking_amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auction(points: Vec<AuctionPoint>) -> AuctionDetails {
+        AuctionDetails { start_time: 1_000, duration: 300, start_rate: 3_000, end_rate: 0, points }
+    }
+
+    fn point(delay: u64, rate: u32) -> AuctionPoint {
+        AuctionPoint { delay, rate }
+    }
+
+    #[test]
+    fn rate_is_flat_outside_the_auction() {
+        let auction = auction(vec![]);
+        assert_eq!(auction.rate_at(0), 3_000);
+        assert_eq!(auction.rate_at(1_000), 3_000);
+        assert_eq!(auction.rate_at(1_300), 0);
+        assert_eq!(auction.rate_at(u64::MAX), 0);
+    }
+
+    #[test]
+    fn rate_decays_linearly_and_rounds_up() {
+        let auction = auction(vec![]);
+        assert_eq!(auction.rate_at(1_001), 2_990);
+        assert_eq!(auction.rate_at(1_150), 1_500);
+        assert_eq!(auction.rate_at(1_299), 10);
+
+        // 1_000 * 1 / 3 is 333.3.., the rate keeps the fraction
+        let short = AuctionDetails { start_time: 0, duration: 3, start_rate: 1_000, end_rate: 0, points: vec![] };
+        assert_eq!(short.rate_at(1), 667);
+    }
+
+    #[test]
+    fn rate_hits_every_point_exactly() {
+        let auction = auction(vec![point(100, 1_000), point(200, 800)]);
+        assert_eq!(auction.rate_at(1_100), 1_000);
+        assert_eq!(auction.rate_at(1_200), 800);
+
+        // segments between the points
+        assert_eq!(auction.rate_at(1_050), 2_000);
+        assert_eq!(auction.rate_at(1_150), 900);
+        assert_eq!(auction.rate_at(1_250), 400);
+    }
+
+    #[test]
+    fn rising_segments_round_down() {
+        let auction = AuctionDetails { start_time: 0, duration: 3, start_rate: 0, end_rate: 1_000, points: vec![] };
+        assert_eq!(auction.rate_at(1), 333);
+        assert_eq!(auction.rate_at(3), 1_000);
+    }
+
+    #[test]
+    fn points_must_be_ordered_and_inside() {
+        assert!(auction(vec![point(100, 1_000), point(200, 800)]).is_valid());
+        assert!(!auction(vec![point(0, 1_000)]).is_valid());
+        assert!(!auction(vec![point(200, 1_000), point(100, 800)]).is_valid());
+        assert!(!auction(vec![point(100, 1_000), point(100, 800)]).is_valid());
+        assert!(!auction(vec![point(300, 1_000)]).is_valid());
+        assert!(!AuctionDetails { duration: 0, ..auction(vec![]) }.is_valid());
+    }
+}
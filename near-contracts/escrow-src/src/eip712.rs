@@ -0,0 +1,295 @@
+use crate::*;
+use auction::AuctionPoint;
+use signed_orders::OrderIntent;
+
+// Domain of typed orders, the salt (keccak256 of the escrow account id)
+// binds signatures to this escrow
+pub const EIP712_DOMAIN_NAME: &str = "NEAR Escrow Src";
+pub const EIP712_DOMAIN_VERSION: &str = "1";
+
+const EIP712_DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,bytes32 salt)";
+const AUCTION_POINT_TYPE: &str = "AuctionPoint(uint64 delay,uint32 rate)";
+const AUCTION_TYPE: &str = "Auction(uint64 startTime,uint64 duration,uint32 startRate,uint32 endRate,AuctionPoint[] points)";
+const ORDER_TYPE: &str = "Order(string rootHash,string token,uint128 totalAmount,uint16 parts,address maker,uint64 expiration,\
+string takingToken,string receiver,uint128 minTakingAmount,Auction auction,uint64 epoch,string exclusiveResolver,uint64 exclusiveUntil,bool convertNear,uint64 nonce,uint64 deadline)";
+
+// Digest a maker signs for an order intent:
+// keccak256("\x19\x01" || domainSeparator || hashStruct(order))
+pub fn order_digest(intent: &OrderIntent) -> [u8; 32] {
+    let mut data = vec![0x19, 0x01];
+    data.extend(domain_separator());
+    data.extend(hash_order(intent));
+    env::keccak256_array(&data)
+}
+
+// NEAR account of an EVM address (eth-implicit account "0x" + 40 hex chars)
+pub fn evm_account(address: &[u8; 20]) -> String {
+    format!("0x{}", hex::encode(address))
+}
+
+// Recovers the signer address from a 65 bytes r || s || v signature
+pub fn recover_address(digest: &[u8; 32], signature: &[u8]) -> Option<[u8; 20]> {
+    if signature.len() != 65 {
+        return None;
+    }
+
+    // wallets produce v as 27/28, raw signers as 0/1
+    let v = match signature[64] {
+        27 | 28 => signature[64] - 27,
+        0 | 1 => signature[64],
+        _ => return None
+    };
+
+    let public_key = env::ecrecover(digest, &signature[..64], v, true)?;
+    let hash = env::keccak256_array(public_key);
+    hash[12..].try_into().ok()
+}
+
+fn domain_separator() -> [u8; 32] {
+    let mut data = type_hash(EIP712_DOMAIN_TYPE).to_vec();
+    data.extend(hash_string(EIP712_DOMAIN_NAME));
+    data.extend(hash_string(EIP712_DOMAIN_VERSION));
+    data.extend(hash_string(env::current_account_id().as_str()));
+    env::keccak256_array(&data)
+}
+
+fn hash_order(intent: &OrderIntent) -> [u8; 32] {
+    let order = &intent.order;
+    let maker = order.maker.as_str().strip_prefix("0x")
+        .and_then(|address| hex::decode(address).ok())
+        .filter(|address| address.len() == 20)
+        .unwrap_or_else(|| env::panic_str("Maker must be an EVM address..."));
+
+    let mut data = type_hash(&format!("{}{}{}", ORDER_TYPE, AUCTION_TYPE, AUCTION_POINT_TYPE)).to_vec();
+    data.extend(hash_string(&order.root_hash));
+    data.extend(hash_string(&order.token.to_string()));
+    data.extend(encode_uint(order.total_amount.as_yoctonear()));
+    data.extend(encode_uint(order.parts as u128));
+    data.extend(encode_address(&maker));
+    data.extend(encode_uint(order.expiration as u128));
+    data.extend(hash_string(&order.taking_token.to_string()));
+    data.extend(hash_string(&order.receiver));
+    data.extend(encode_uint(order.min_taking_amount.as_yoctonear()));
+    data.extend(hash_auction(order.auction.as_ref()));
+    data.extend(encode_uint(order.epoch as u128));
+    // orders without an exclusive resolver sign an empty resolver and 0
+    let (exclusive_resolver, exclusive_until) = order.exclusive_resolver.as_ref()
+        .map_or(("", 0), |exclusive| (exclusive.resolver.as_str(), exclusive.until));
+    data.extend(hash_string(exclusive_resolver));
+    data.extend(encode_uint(exclusive_until as u128));
+    data.extend(encode_uint(order.convert_near as u128));
+    data.extend(encode_uint(intent.nonce as u128));
+    data.extend(encode_uint(intent.deadline as u128));
+    env::keccak256_array(&data)
+}
+
+// Orders without an auction sign an all-zero Auction, which is never a valid auction
+fn hash_auction(auction: Option<&AuctionDetails>) -> [u8; 32] {
+    let (start_time, duration, start_rate, end_rate, points) = match auction {
+        Some(auction) => (auction.start_time, auction.duration, auction.start_rate, auction.end_rate, auction.points.as_slice()),
+        None => (0, 0, 0, 0, [].as_slice())
+    };
+
+    let mut data = type_hash(&format!("{}{}", AUCTION_TYPE, AUCTION_POINT_TYPE)).to_vec();
+    data.extend(encode_uint(start_time as u128));
+    data.extend(encode_uint(duration as u128));
+    data.extend(encode_uint(start_rate as u128));
+    data.extend(encode_uint(end_rate as u128));
+    data.extend(hash_auction_points(points));
+    env::keccak256_array(&data)
+}
+
+fn hash_auction_points(points: &[AuctionPoint]) -> [u8; 32] {
+    let point_type_hash = type_hash(AUCTION_POINT_TYPE);
+    let mut data = Vec::with_capacity(points.len() * 32);
+    for point in points {
+        let mut point_data = point_type_hash.to_vec();
+        point_data.extend(encode_uint(point.delay as u128));
+        point_data.extend(encode_uint(point.rate as u128));
+        data.extend(env::keccak256_array(&point_data));
+    }
+    env::keccak256_array(&data)
+}
+
+fn type_hash(encoded_type: &str) -> [u8; 32] {
+    env::keccak256_array(encoded_type.as_bytes())
+}
+
+fn hash_string(value: &str) -> [u8; 32] {
+    env::keccak256_array(value.as_bytes())
+}
+
+fn encode_uint(value: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn encode_address(address: &[u8]) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(address);
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+    use secp256k1::{ecdsa::RecoveryId, Message, PublicKey, Secp256k1, SecretKey};
+
+    use super::*;
+
+    // secp256k1 group order
+    const CURVE_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+        0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41
+    ];
+
+    fn secret_key() -> SecretKey {
+        SecretKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn address(secret_key: &SecretKey) -> [u8; 20] {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), secret_key).serialize_uncompressed();
+        env::keccak256_array(&public_key[1..])[12..].try_into().unwrap()
+    }
+
+    // r || s || v with v as the raw recovery id
+    fn sign(secret_key: &SecretKey, digest: &[u8; 32]) -> Vec<u8> {
+        let message = Message::from_slice(digest).unwrap();
+        let (recovery_id, signature) = Secp256k1::new().sign_ecdsa_recoverable(&message, secret_key).serialize_compact();
+        let mut signature = signature.to_vec();
+        signature.push(recovery_id.to_i32() as u8);
+        signature
+    }
+
+    // same signature with s replaced by n - s, recovering the same key with the other v
+    fn high_s(signature: &[u8]) -> Vec<u8> {
+        let mut high_s = signature.to_vec();
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let diff = CURVE_ORDER[i] as i16 - signature[32 + i] as i16 - borrow;
+            high_s[32 + i] = diff.rem_euclid(256) as u8;
+            borrow = (diff < 0) as i16;
+        }
+        high_s[64] ^= 1;
+        high_s
+    }
+
+    fn intent(maker: &[u8; 20]) -> OrderIntent {
+        OrderIntent {
+            order: MakerOrder {
+                root_hash: "hashlock".to_string(),
+                token: Asset::Ft("token.near".parse().unwrap()),
+                total_amount: NearToken::from_yoctonear(1_000),
+                parts: 1,
+                filled_amount: ZERO_NEAR,
+                withdrawn_amount: ZERO_NEAR,
+                cancelled_amount: ZERO_NEAR,
+                maker: evm_account(maker).parse().unwrap(),
+                expiration: 1_000,
+                taking_token: Asset::Foreign("0xtoken".to_string()),
+                receiver: "0xreceiver".to_string(),
+                min_taking_amount: NearToken::from_yoctonear(900),
+                auction: None,
+                epoch: 0,
+                exclusive_resolver: None,
+                convert_near: false
+            },
+            nonce: 1,
+            deadline: 2_000
+        }
+    }
+
+    fn set_escrow(account: &str) {
+        testing_env!(VMContextBuilder::new().current_account_id(account.parse().unwrap()).build());
+    }
+
+    #[test]
+    fn encodes_like_solidity() {
+        // keccak256 of the EIP-712 domain type used by most dapps
+        assert_eq!(
+            hex::encode(type_hash("EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)")),
+            "8b73c3c69bb8fe3d512ecc4cf759cc79239f7b179b0ffacaa9a75d522b39400f"
+        );
+        assert_eq!(encode_uint(0x0102)[30..], [0x01, 0x02]);
+        assert_eq!(encode_uint(u128::MAX)[..16], [0; 16]);
+        assert_eq!(encode_address(&[0xaa; 20])[..12], [0; 12]);
+        assert_eq!(encode_address(&[0xaa; 20])[12..], [0xaa; 20]);
+    }
+
+    #[test]
+    fn digest_binds_escrow_and_fields() {
+        let maker = address(&secret_key());
+        set_escrow("escrow.near");
+        let digest = order_digest(&intent(&maker));
+        assert_eq!(digest, order_digest(&intent(&maker)));
+
+        let mut other = intent(&maker);
+        other.nonce = 2;
+        assert_ne!(digest, order_digest(&other));
+
+        let mut other = intent(&maker);
+        other.order.receiver = "0xother".to_string();
+        assert_ne!(digest, order_digest(&other));
+
+        let mut other = intent(&maker);
+        other.order.auction = Some(AuctionDetails { start_time: 0, duration: 0, start_rate: 0, end_rate: 0, points: vec![] });
+        assert_eq!(digest, order_digest(&other), "no auction signs as an all-zero auction");
+        other.order.auction = Some(AuctionDetails { start_time: 0, duration: 10, start_rate: 1, end_rate: 0, points: vec![] });
+        assert_ne!(digest, order_digest(&other));
+
+        set_escrow("other-escrow.near");
+        assert_ne!(digest, order_digest(&intent(&maker)));
+    }
+
+    #[test]
+    fn recovers_the_maker_for_every_v_encoding() {
+        let key = secret_key();
+        let maker = address(&key);
+        set_escrow("escrow.near");
+        let intent = intent(&maker);
+        let digest = order_digest(&intent);
+
+        let signature = sign(&key, &digest);
+        assert_eq!(recover_address(&digest, &signature), Some(maker));
+        assert_eq!(evm_account(&maker), intent.order.maker.as_str());
+
+        let mut wallet_signature = signature.clone();
+        wallet_signature[64] += 27;
+        assert_eq!(recover_address(&digest, &wallet_signature), Some(maker));
+
+        // the other recovery id recovers some other key
+        let mut flipped = signature.clone();
+        flipped[64] ^= 1;
+        assert_ne!(recover_address(&digest, &flipped), Some(maker));
+        flipped[64] += 27;
+        assert_ne!(recover_address(&digest, &flipped), Some(maker));
+    }
+
+    #[test]
+    fn high_s_signatures_are_rejected() {
+        let key = secret_key();
+        let digest = [3; 32];
+        let signature = sign(&key, &digest);
+        let high_s = high_s(&signature);
+
+        // the malleated signature is valid for the curve, but not accepted here
+        let recovery_id = RecoveryId::from_i32(high_s[64] as i32).unwrap();
+        assert!(secp256k1::ecdsa::RecoverableSignature::from_compact(&high_s[..64], recovery_id).is_ok());
+        assert_eq!(recover_address(&digest, &signature), Some(address(&key)));
+        assert_eq!(recover_address(&digest, &high_s), None);
+    }
+
+    #[test]
+    fn malformed_signatures_are_rejected() {
+        let digest = [3; 32];
+        let signature = sign(&secret_key(), &digest);
+
+        assert_eq!(recover_address(&digest, &signature[..64]), None);
+        for v in [2, 26, 29] {
+            let mut signature = signature.clone();
+            signature[64] = v;
+            assert_eq!(recover_address(&digest, &signature), None);
+        }
+    }
+}
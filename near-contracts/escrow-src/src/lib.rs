@@ -1,30 +1,83 @@
 use std::str::FromStr;
 
-use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, log, near_bindgen, require, serde::{Deserialize, Serialize}, store::LookupMap, AccountId, Gas, NearSchema, NearToken, Promise, PromiseOrValue};
-use shared_lib::{fungible_tokens::{ext_ft, StorageBalance}, immutables::Immutables, merkle_verifier::MerkleVerifier};
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, ext_contract, log, near_bindgen, require, serde::{Deserialize, Serialize}, store::{IterableMap, LookupMap, LookupSet}, AccountId, Gas, NearSchema, NearToken, Promise, PromiseError, PromiseOrValue, PublicKey};
+use shared_lib::{assets::Asset, escrow_status::EscrowStatus, fungible_tokens::{ext_ft, StorageBalance}, immutables::{Immutables, Stage, TimeLock}, events::{CancelledEvent, EscrowCreatedEvent, EscrowEvent, OrderEvent, OrderFilledEvent, SafetyDepositEvent, WithdrawnEvent}, merkle_verifier::MerkleVerifier, near_wrapper::NearWrapper, resolver_registry::ResolverRegistry, secondary_index::SecondaryIndex, secret_registry::SecretRegistry};
 
 pub mod ft_functions;
+pub mod auction;
+pub mod signed_orders;
+pub mod eip712;
+pub mod nonces;
+pub mod resolvers;
+pub mod secrets;
+pub mod views;
+pub mod assets;
+pub mod staking;
+pub mod wrap_near;
+pub mod migration;
+
+use assets::NFT_AMOUNT;
+use auction::AuctionDetails;
+use migration::{LegacyMakerOrder, LegacyResolverOrderFill, LEGACY_MAKERS_ORDERS_PREFIX, LEGACY_RESOLVER_ORDERS_PREFIX};
+use signed_orders::SignedMakerOrder;
+use staking::{ResolverStake, StakeRate, DEFAULT_SLASH_BPS};
+
+// ft_on_transfer message crediting the sender's balance for signed orders,
+// "deposit:<account>" credits another maker (e.g. the eth-implicit account of an EVM maker)
+const DEPOSIT_MSG: &str = "deposit";
+
+const ZERO_NEAR: NearToken = NearToken::from_yoctonear(0);
+const GAS_FOR_RESOLVE_CANCEL_ORDER: Gas = Gas::from_tgas(10);
+const GAS_FOR_RESOLVE_SETTLEMENT: Gas = Gas::from_tgas(15);
+
+#[ext_contract(ext_escrow)]
+trait _EscrowCallbacks {
+    fn on_cancel_order_resolved(&mut self, order_root_hash: String, amount: NearToken, event: EscrowEvent) -> bool;
+
+    fn on_settlement_resolved(&mut self, immutables_hash: String, safty_deposit_receiver: AccountId, slash_stake: bool, event: EscrowEvent) -> bool;
+}
 
 // Main User Order
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Clone)]
 #[serde(crate = "near_sdk::serde")]
 #[borsh(crate = "near_sdk::borsh")]
 pub struct MakerOrder {
     root_hash: String,              // hashlock for single, merkle_root for multi fill
-    token: AccountId,               // token used by maker to make exchange
+    token: Asset,                   // asset used by maker to make exchange (see shared_lib::assets)
     total_amount: NearToken,        // total tokens maker is putting
     parts: u16,                     // parts the order is devided in (default 1)
     filled_amount: NearToken,       // taker placed amount
-    withdrawn_amount: NearToken,    // withdrawn amount
+    withdrawn_amount: NearToken,    // amount released by withdrawn or cancelled fills
+    cancelled_amount: NearToken,    // unfilled remainder refunded to the maker on cancellation
     maker: AccountId,               // maker account
-    expiration: u64                 // timestamp beyond which user can run do self withdrawal
+    expiration: u64,                // timestamp beyond which user can run do self withdrawal
+    taking_token: Asset,            // token the maker wants on the destination chain
+    receiver: String,               // maker's receiving address on the destination chain
+    min_taking_amount: NearToken,   // least taking amount accepted for the whole order
+    auction: Option<AuctionDetails>,// dutch auction on top of min_taking_amount (optional)
+    epoch: u64,                     // maker epoch the order belongs to, see increase_epoch
+    exclusive_resolver: Option<ExclusiveResolver>, // resolver allowed to fill alone for a while (optional)
+    convert_near: bool              // native NEAR orders pay resolvers wNEAR, wNEAR orders refund the maker NEAR
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+// Window at the start of an order in which only one resolver may fill it
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct ExclusiveResolver {
+    resolver: AccountId,
+    until: u64                      // timestamp after which any registered resolver may fill
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Clone)]
 #[serde(crate = "near_sdk::serde")]
 #[borsh(crate = "near_sdk::borsh")]
 pub struct ResolverOrderFill {
-    immutables: Immutables
+    immutables: Immutables,
+    status: EscrowStatus,           // settlement state, only Active escrows can be settled
+    locked_stake: NearToken,        // taker's stake backing this fill, see staking.rs
+    wrap_account: Option<AccountId>,// wNEAR contract of a convert_near order, pinned when the fill is placed
+    held_asset: Asset               // asset the making amount is held in, converted by a failed payout
 }
 
 #[near_bindgen]
@@ -34,19 +87,90 @@ pub struct EscrowSrc {
     // orders placed by makers
     // delete entry once order amount is fully withdrawn
     // entry key: maker_order.root_hash
-    pub makers_orders: LookupMap<String, MakerOrder>,
+    pub makers_orders: IterableMap<String, MakerOrder>,
 
     // fill-orders placed by resolvers
     // delete entry once a fill order is withdrawn or cancelled
     // entry key: resolver_order_fill.immutables.hash()
-    pub resolver_orders: LookupMap<String, ResolverOrderFill>
+    pub resolver_orders: IterableMap<String, ResolverOrderFill>,
+
+    // root hashes of makers_orders by maker and by token, for the filtered order views
+    pub orders_by_maker: SecondaryIndex<AccountId>,
+    pub orders_by_token: SecondaryIndex<Asset>,
+
+    // immutables hashes of resolver_orders by taker
+    pub fills_by_taker: SecondaryIndex<AccountId>,
+
+    // tokens deposited by makers for signed orders, not locked by any order yet
+    // entry key: (maker, token)
+    pub maker_balances: LookupMap<(AccountId, AccountId), NearToken>,
+
+    // ed25519 keys makers sign order intents with
+    pub maker_public_keys: LookupSet<(AccountId, PublicKey)>,
+
+    // used intent nonces, 128 nonces per entry
+    // entry key: (maker, nonce / 128)
+    pub nonce_bitmaps: LookupMap<(AccountId, u64), u128>,
+
+    // intents with a lower nonce are invalid
+    // entry key: maker
+    pub min_nonces: LookupMap<AccountId, u64>,
+
+    // orders of an older epoch are invalid
+    // entry key: maker
+    pub maker_epochs: LookupMap<AccountId, u64>,
+
+    // resolvers allowed to fill orders, managed by the owner
+    pub resolver_registry: ResolverRegistry,
+
+    // NEAR bonded by resolvers, partly locked by their active fills
+    // entry key: resolver
+    pub resolver_stakes: LookupMap<AccountId, ResolverStake>,
+
+    // stake required per making token volume, set by the owner
+    // entry key: making asset
+    pub stake_rates: LookupMap<Asset, StakeRate>,
+
+    // share of a fill's locked stake slashed to the maker on public cancellation
+    pub slash_bps: u16,
+
+    // secrets revealed by withdrawals, keyed by hashlock and by immutables hash
+    pub revealed_secrets: SecretRegistry,
+
+    // wNEAR contract used to wrap/unwrap payouts of convert_near orders, set by the owner
+    pub near_wrapper: NearWrapper,
+
+    // asset a maker refund was converted to while its transfer is pending
+    // entry key: maker_order.root_hash
+    pub converted_refunds: LookupMap<String, Asset>,
+
+    // orders and fills of the first deployed layout, left at their old prefixes (see migration.rs)
+    pub legacy_makers_orders: LookupMap<String, LegacyMakerOrder>,
+    pub legacy_resolver_orders: LookupMap<String, LegacyResolverOrderFill>
 }
 
 impl Default for EscrowSrc {
     fn default() -> Self {
         Self {
-            makers_orders: LookupMap::new(b"m"),
-            resolver_orders: LookupMap::new(b"r")
+            makers_orders: IterableMap::new(b"a"),
+            resolver_orders: IterableMap::new(b"c"),
+            orders_by_maker: SecondaryIndex::new(b"o"),
+            orders_by_token: SecondaryIndex::new(b"p"),
+            fills_by_taker: SecondaryIndex::new(b"q"),
+            maker_balances: LookupMap::new(b"b"),
+            maker_public_keys: LookupSet::new(b"k"),
+            nonce_bitmaps: LookupMap::new(b"n"),
+            min_nonces: LookupMap::new(b"f"),
+            maker_epochs: LookupMap::new(b"e"),
+            resolver_registry: ResolverRegistry::new(b"w", env::current_account_id()),
+            resolver_stakes: LookupMap::new(b"s"),
+            stake_rates: LookupMap::new(b"t"),
+            slash_bps: DEFAULT_SLASH_BPS,
+            revealed_secrets: SecretRegistry::new(b"x"),
+            near_wrapper: NearWrapper::default(),
+            converted_refunds: LookupMap::new(b"y"),
+            legacy_makers_orders: LookupMap::new(LEGACY_MAKERS_ORDERS_PREFIX),
+            legacy_resolver_orders: LookupMap::new(LEGACY_RESOLVER_ORDERS_PREFIX)
         }
     }
 }
@@ -58,6 +182,7 @@ impl EscrowSrc {
     // It expects a hex-encoded string representing the maker order
     // If the order is valid, it stores the order in the lookup map
     // If the order is invalid, it returns the transferred amount back to the sender
+    // A "deposit" message credits the sender's balance used by signed orders instead
     pub fn ft_on_transfer(
         &mut self, 
         sender_id: AccountId, 
@@ -65,41 +190,19 @@ impl EscrowSrc {
         msg: String
     ) -> PromiseOrValue<NearToken>  {
 
-        // Validate hex string
-        let bytes_hex = hex::decode(msg);
-        if bytes_hex.is_err() {
-            log!("Invlid hex string provided");
-            return PromiseOrValue::Value(amount);
-        }
-
-        // Deserialize the maker order from the hex string
-        // This will fail if the data is not a valid MakerOrder
-        let maker_order = MakerOrder::try_from_slice(&bytes_hex.unwrap());
-        if maker_order.is_err() {
-            log!("Invalid maker order data");
-            return PromiseOrValue::Value(amount);
+        if msg == DEPOSIT_MSG {
+            self.credit_maker_balance(&sender_id, &env::predecessor_account_id(), amount);
+            return PromiseOrValue::Value(ZERO_NEAR);
         }
 
-        let maker_order = maker_order.unwrap();
-
-        require!(maker_order.maker == sender_id, "Maker order does not match the sender ID");
-        require!(maker_order.total_amount <= amount, "Maker order total amount is greater than the transferred amount");
-        require!(maker_order.token == env::predecessor_account_id(), "Invalid token");
-        require!(maker_order.filled_amount == NearToken::from_yoctonear(0), "Maker order is already filled");
-        require!(maker_order.expiration > env::block_timestamp() + 500, "Maker order has expired");
-
-        // Return unused tokens if any
-        let unused_tokens = amount.checked_sub(maker_order.total_amount);
-
-        if unused_tokens.is_some() && unused_tokens.unwrap() > NearToken::from_yoctonear(0) {
-            log!("Unused tokens detected: {}", unused_tokens.unwrap());
-            return PromiseOrValue::Value(unused_tokens.unwrap());
+        if let Some(maker) = msg.strip_prefix(DEPOSIT_MSG).and_then(|maker| maker.strip_prefix(':')) {
+            let maker = AccountId::from_str(maker).expect("Invalid deposit account...");
+            self.credit_maker_balance(&maker, &env::predecessor_account_id(), amount);
+            return PromiseOrValue::Value(ZERO_NEAR);
         }
 
-        // Store the maker order in the lookup map
-        self.makers_orders.insert(maker_order.root_hash.clone(), maker_order);
-
-        PromiseOrValue::Value(NearToken::from_yoctonear(0))    
+        let token = Asset::Ft(env::predecessor_account_id());
+        PromiseOrValue::Value(self.place_transferred_order(&sender_id, token, amount, msg))
     }
 
 
@@ -108,17 +211,30 @@ impl EscrowSrc {
         &mut self,
         immutables: Immutables, 
         idx: Option<u16>,                           // index of secret being used (multi-fill)
-        merkle_proof: Option<Vec<String>>           // merkle proof (multi-fill)
+        merkle_proof: Option<Vec<String>>,          // merkle proof (multi-fill)
+        signed_order: Option<SignedMakerOrder>      // maker's signed order, placed before the fill
     ) {
         // first check if safty deposit is there
         let attached_deposit = env::attached_deposit();
         require!(attached_deposit == immutables.src_safty_deposit,
         "Invalid or no safty deposit...!");
 
+        // a signed order is verified and funded from the maker's balance first
+        if let Some(signed_order) = signed_order {
+            self.place_signed_order(signed_order);
+        }
+
         // check if maker order exists to fill
         require!(self.makers_orders.contains_key(&immutables.order_root_hash), "Order doesn't exist...");
 
         let maker_order = self.makers_orders.get(&immutables.order_root_hash).unwrap();
+
+        // expired orders can only be reclaimed by the maker
+        require!(env::block_timestamp() < maker_order.expiration, "Maker order has expired...");
+        // bulk invalidated or cancelled by the maker
+        require!(self.is_current_epoch(maker_order), "Maker order was invalidated by the maker...");
+        require!(maker_order.cancelled_amount == ZERO_NEAR, "Maker order was cancelled by the maker...");
+
         let mut total_amount = NearToken::from_yoctonear(0);
         let mut filled_amount = NearToken::from_yoctonear(0);
         let making_amount = &immutables.making_amount;
@@ -126,12 +242,37 @@ impl EscrowSrc {
         let parts = maker_order.parts;
 
         if let Some(maker_order) = self.makers_orders.get(&immutables.order_root_hash) {
-            total_amount = maker_order.total_amount.clone();
-            filled_amount = maker_order.filled_amount.clone();
+            total_amount = maker_order.total_amount;
+            filled_amount = maker_order.filled_amount;
         }
 
 
-        // TODO: validate Immutables
+        // immutables must describe a fill of this very order, placed by the caller
+        require!(immutables.taker == env::predecessor_account_id().as_str(), "Taker must be the caller...");
+        self.resolver_registry.assert_active(&env::predecessor_account_id());
+        if let Some(exclusive) = maker_order.exclusive_resolver.as_ref().filter(|exclusive| env::block_timestamp() < exclusive.until) {
+            require!(exclusive.resolver == env::predecessor_account_id(), "Order is exclusive to another resolver...");
+        }
+        require!(immutables.maker == maker_order.maker.as_str(), "Maker doesn't match the order...");
+        require!(immutables.making_token == maker_order.token, "Making token doesn't match the order...");
+        require!(immutables.taking_token == maker_order.taking_token, "Taking token doesn't match the order...");
+        require!(immutables.receiver == maker_order.receiver, "Receiver doesn't match the order...");
+        require!(making_amount.as_yoctonear() > 0, "Making amount must be greater than zero...");
+
+        // never lock more than the maker put in
+        let new_filled_amount = filled_amount.checked_add(*making_amount)
+            .expect("Overflow when calculating new filled amount");
+        require!(new_filled_amount <= total_amount, "Making amount exceeds the unfilled amount of the order...");
+
+        // the maker gets at least the current auction price for this part, rounded in maker's favour
+        let order_taking_amount = Self::current_order_taking_amount(maker_order);
+        let min_taking_amount = Self::min_taking_amount(&order_taking_amount, &total_amount, making_amount);
+        require!(immutables.taking_amount >= min_taking_amount, "Taking amount is below the order price...");
+
+        // timelock phases must be ordered and consistent with the destination chain
+        if let Err(err) = immutables.timelock.verify() {
+            env::panic_str(&err.to_string());
+        }
 
 
         // if its multi fill check if idx of secret is correct
@@ -156,16 +297,48 @@ impl EscrowSrc {
             require!(immutables.hashlock == maker_order.root_hash, "Invalid Hashlock...");
         }
 
-        // place the order
-        self.resolver_orders.insert(immutables.hash(), ResolverOrderFill { immutables: immutables.clone() });
+        // convert_near fills settle through the wNEAR contract of today, whatever the owner sets later
+        let wrap_account = Self::order_wrap_account(maker_order, self.near_wrapper.wrap_account());
+        require!(!maker_order.convert_near || wrap_account.is_some(), "wNEAR contract is not configured...");
+
+        // back the fill with the taker's stake
+        let locked_stake = self.required_stake(&maker_order.token, making_amount);
+        self.lock_stake(&env::predecessor_account_id(), locked_stake);
+
+        // place the order, its timelocks count from now on
+        let immutables_hash = immutables.hash();
+        require!(!self.resolver_orders.contains_key(&immutables_hash), "Escrow already exists...");
+        let mut escrow_immutables = immutables.clone();
+        escrow_immutables.timelock.set_deployed_at();
+
+        EscrowEvent::SrcEscrowCreated(
+            EscrowCreatedEvent::new(immutables_hash.clone(), &escrow_immutables, attached_deposit)
+        ).emit();
+        EscrowEvent::SafetyDepositAdded(SafetyDepositEvent {
+            immutables_hash: immutables_hash.clone(),
+            account_id: env::predecessor_account_id(),
+            amount: attached_deposit
+        }).emit();
+        EscrowEvent::OrderFilled(OrderFilledEvent {
+            order_root_hash: root_hash.clone(),
+            immutables_hash: immutables_hash.clone(),
+            taker: env::predecessor_account_id(),
+            making_amount: *making_amount,
+            filled_amount: new_filled_amount
+        }).emit();
+
+        self.fills_by_taker.insert(&env::predecessor_account_id(), immutables_hash.clone());
+        self.resolver_orders.insert(immutables_hash, ResolverOrderFill {
+            immutables: escrow_immutables,
+            status: EscrowStatus::Active,
+            locked_stake,
+            wrap_account,
+            held_asset: immutables.making_token.clone()
+        });
 
         // add as filled amount in maker order
         if let Some(value) = self.makers_orders.get_mut(&root_hash.clone()) {
-            let filled_amount_u128: u128 = filled_amount.as_yoctonear();
-            let making_amount_u128: u128 = making_amount.as_yoctonear();
-            let new_filled_amount = filled_amount_u128.checked_add(making_amount_u128)
-                .expect("Overflow when calculating new filled amount");
-            value.filled_amount = NearToken::from_yoctonear(new_filled_amount);
+            value.filled_amount = new_filled_amount;
         }
     }
 
@@ -176,24 +349,20 @@ impl EscrowSrc {
      * --/-- private cancellation --/-- public cancellation ----
      */
     #[payable]
-    pub fn withdraw(&mut self, secret: String, immutables: Immutables) {
+    pub fn withdraw(&mut self, secret: Option<String>, immutables: Immutables) -> Promise {
         // only taker can call it
         require!(env::predecessor_account_id() == immutables.taker, "Only taker can withdraw...",);
-        require!(shared_lib::utils::_only_after(immutables.timelock.src_withdrawal));
-        require!(shared_lib::utils::_only_before(immutables.timelock.src_cancellation));
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::SrcWithdrawal));
+        require!(shared_lib::utils::_only_before(&timelock, Stage::SrcCancellation));
 
-        // validate secret
-        require!(shared_lib::utils::validate_secret(secret, immutables.hashlock), "Invalid secret...");
+        // validate secret, a secret already revealed for the hashlock can be reused
+        let secret = self.reveal_secret(secret, &immutables, &timelock);
 
         // withdraw tokens
-        self.safe_ft_transfer(
-            AccountId::from_str(&immutables.making_token).expect("invalid token..."), 
-            AccountId::from_str(&immutables.taker).expect("Invalid receiver account..."), 
-            immutables.making_amount
-        );
-
-        // release safty deposit
-        Promise::new(env::predecessor_account_id()).transfer(immutables.src_safty_deposit);
+        let receiver_id = AccountId::from_str(&immutables.taker).expect("Invalid receiver account...");
+        let event = Self::withdrawn_event(&immutables, secret, receiver_id.clone());
+        self.settle_escrow(&immutables, EscrowStatus::Withdrawn, receiver_id, false, event)
     }
 
 
@@ -203,24 +372,19 @@ impl EscrowSrc {
      * --/-- private cancellation --/-- public cancellation ----
      */
     #[payable]
-    pub fn withdraw_to(&mut self, secret: String, immutables: Immutables, target: AccountId) {
+    pub fn withdraw_to(&mut self, secret: Option<String>, immutables: Immutables, target: AccountId) -> Promise {
         // only taker can call it
         require!(env::predecessor_account_id() == immutables.taker, "Only taker can withdraw...",);
-        require!(shared_lib::utils::_only_after(immutables.timelock.src_withdrawal));
-        require!(shared_lib::utils::_only_before(immutables.timelock.src_cancellation));
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::SrcWithdrawal));
+        require!(shared_lib::utils::_only_before(&timelock, Stage::SrcCancellation));
         
-        // validate secret
-        require!(shared_lib::utils::validate_secret(secret, immutables.hashlock), "Invalid secret...");
+        // validate secret, a secret already revealed for the hashlock can be reused
+        let secret = self.reveal_secret(secret, &immutables, &timelock);
         
         // withdraw tokens
-        self.safe_ft_transfer(
-            AccountId::from_str(&immutables.making_token).expect("invalid token..."), 
-            target, 
-            immutables.making_amount
-        );
-
-        // release safty deposit
-        Promise::new(env::predecessor_account_id()).transfer(immutables.src_safty_deposit);
+        let event = Self::withdrawn_event(&immutables, secret, target.clone());
+        self.settle_escrow(&immutables, EscrowStatus::Withdrawn, target, false, event)
     }
 
     /**
@@ -229,24 +393,20 @@ impl EscrowSrc {
      * --/-- private cancellation --/-- public cancellation ----
      */
     #[payable]
-    pub fn pubic_withdraw(&mut self, secret: String, immutables: Immutables) {
+    pub fn pubic_withdraw(&mut self, secret: Option<String>, immutables: Immutables) -> Promise {
         // anyone can call it
+        let timelock = self.escrow_timelock(&immutables);
+
+        require!(shared_lib::utils::_only_after(&timelock, Stage::SrcPublicWithdrawal));
+        require!(shared_lib::utils::_only_before(&timelock, Stage::SrcCancellation));
         
-        require!(shared_lib::utils::_only_after(immutables.timelock.src_public_withdrawal));
-        require!(shared_lib::utils::_only_before(immutables.timelock.src_cancellation));
-        
-        // validate secret
-        require!(shared_lib::utils::validate_secret(secret, immutables.hashlock), "Invalid secret...");
+        // validate secret, a secret already revealed for the hashlock can be reused
+        let secret = self.reveal_secret(secret, &immutables, &timelock);
         
         // withdraw tokens
-        self.safe_ft_transfer(
-            AccountId::from_str(&immutables.making_token).expect("invalid token..."), 
-            AccountId::from_str(&immutables.taker).expect("Invalid receiver account..."), 
-            immutables.making_amount
-        );
-
-        // release safty deposit
-        Promise::new(env::predecessor_account_id()).transfer(immutables.src_safty_deposit);
+        let receiver_id = AccountId::from_str(&immutables.taker).expect("Invalid receiver account...");
+        let event = Self::withdrawn_event(&immutables, secret, receiver_id.clone());
+        self.settle_escrow(&immutables, EscrowStatus::Withdrawn, receiver_id, false, event)
     }
     
     /**
@@ -254,21 +414,16 @@ impl EscrowSrc {
      * ---- contract deployed --/-- finality --/-- private withdrawal --/-- public withdrawal --/--
      * --/-- PRIVATE CANCELLATION --/-- PUBLIC CANCELLATION ----
      */
-    pub fn cancel(&mut self, immutables: Immutables) {
+    pub fn cancel(&mut self, immutables: Immutables) -> Promise {
         // only taker can call it
         require!(env::predecessor_account_id() == immutables.taker, "Only taker can cancel...");
-        require!(shared_lib::utils::_only_after(immutables.timelock.src_cancellation));
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::SrcCancellation));
 
         // send maker's assets back
-        let makers_token = AccountId::from_str(&immutables.making_token.clone())
-            .expect("invalid token...");
         let maker = AccountId::from_str(&immutables.maker).expect("Invalid receiver account...");
-        ext_ft::ext(makers_token)
-            .with_static_gas(Gas::from_tgas(30))
-            .ft_transfer(maker, immutables.making_amount, Some("Order Cancelled".to_string()));
-
-        // release safty deposit
-        Promise::new(env::predecessor_account_id()).transfer(immutables.src_safty_deposit);        
+        let event = EscrowEvent::Cancelled(Self::cancelled_event(&immutables, maker.clone()));
+        self.settle_escrow(&immutables, EscrowStatus::Cancelled, maker, false, event)
     }
 
     /**
@@ -276,22 +431,154 @@ impl EscrowSrc {
      * ---- contract deployed --/-- finality --/-- private withdrawal --/-- public withdrawal --/--
      * --/-- private cancellation --/-- PUBLIC CANCELLATION ----
      */
-    pub fn public_cancel(&mut self, immutables: Immutables) {
+    pub fn public_cancel(&mut self, immutables: Immutables) -> Promise {
         // anyone can call it
 
-        // only after Timelock.src_cancellation
-        require!(shared_lib::utils::_only_after(immutables.timelock.src_public_cancellation));
+        // only after Timelock.src_public_cancellation
+        let timelock = self.escrow_timelock(&immutables);
+        require!(shared_lib::utils::_only_after(&timelock, Stage::SrcPublicCancellation));
         
-        // send maker's assets back
-        let makers_token = AccountId::from_str(&immutables.making_token.clone())
-            .expect("invalid token...");
+        // send maker's assets back, the taker let the escrow run into public cancellation
+        // so part of its stake goes to the maker as well
         let maker = AccountId::from_str(&immutables.maker).expect("Invalid receiver account...");
-        ext_ft::ext(makers_token)
-            .with_static_gas(Gas::from_tgas(30))
-            .ft_transfer(maker, immutables.making_amount, Some("Order Cancelled".to_string()));
+        let event = EscrowEvent::PublicCancelled(Self::cancelled_event(&immutables, maker.clone()));
+        self.settle_escrow(&immutables, EscrowStatus::Cancelled, maker, true, event)
+    }
+
+    // Finalizes a withdrawal or cancellation once the token transfer has settled.
+    // On success the escrow is removed, the safety deposit goes to whoever settled it and the
+    // settlement event is emitted; on failure the escrow is re-activated so it can be settled again.
+    #[private]
+    pub fn on_settlement_resolved(
+        &mut self,
+        immutables_hash: String,
+        safty_deposit_receiver: AccountId,
+        slash_stake: bool,
+        event: EscrowEvent,
+        #[callback_result] transfer_result: Result<(), PromiseError>
+    ) -> bool {
+        if transfer_result.is_err() {
+            log!("Settlement of escrow {} failed, escrow is active again", immutables_hash);
+            if let Some(fill) = self.resolver_orders.get_mut(&immutables_hash) {
+                fill.status = EscrowStatus::Active;
+            }
+            return false;
+        }
+
+        let fill = self.resolver_orders.remove(&immutables_hash)
+            .expect("Escrow doesn't exist...");
+        let immutables = fill.immutables;
+        event.emit();
+
+        // settled fill has left the escrow, drop the maker order once nothing is locked anymore
+        let root_hash = &immutables.order_root_hash;
+        if let Some(maker_order) = self.makers_orders.get_mut(root_hash) {
+            maker_order.withdrawn_amount = maker_order.withdrawn_amount.checked_add(immutables.making_amount)
+                .expect("Overflow when calculating withdrawn amount");
+
+            if Self::is_order_closed(maker_order) {
+                self.remove_maker_order(root_hash);
+            }
+        }
 
         // release safty deposit
-        Promise::new(env::predecessor_account_id()).transfer(immutables.src_safty_deposit);
+        Promise::new(safty_deposit_receiver).transfer(immutables.src_safty_deposit).detach();
+
+        // release the taker's stake, slashed part goes to the maker
+        let taker = AccountId::from_str(&immutables.taker).expect("Invalid taker account...");
+        self.fills_by_taker.remove(&taker, &immutables_hash);
+        let slashed = self.release_stake(&taker, fill.locked_stake, slash_stake);
+        if slashed > ZERO_NEAR {
+            let maker = AccountId::from_str(&immutables.maker).expect("Invalid maker account...");
+            Promise::new(maker).transfer(slashed).detach();
+        }
+
+        true
+    }
+
+    /**
+     * @dev Lets the maker cancel the unfilled part of an order at any time. Tokens already
+     * locked by resolver fills stay in escrow and follow their own timelocks; the remainder
+     * is refunded and recorded as cancelled_amount, so total_amount (and with it the partial
+     * fill indexes of pending fills) never changes. No fill can be placed afterwards.
+     */
+    pub fn cancel_maker_order(&mut self, order_root_hash: String) -> Promise {
+        let maker_order = self.makers_orders.get_mut(&order_root_hash)
+            .expect("Order doesn't exist...");
+
+        // only maker can call it
+        require!(env::predecessor_account_id() == maker_order.maker, "Only maker can cancel...");
+        require!(maker_order.cancelled_amount == ZERO_NEAR, "Order is already cancelled...");
+
+        let unfilled_amount = maker_order.total_amount.checked_sub(maker_order.filled_amount)
+            .expect("Overflow when calculating unfilled amount");
+        require!(unfilled_amount > ZERO_NEAR, "Nothing to cancel...");
+
+        maker_order.cancelled_amount = unfilled_amount;
+
+        let event = if env::block_timestamp() > maker_order.expiration { EscrowEvent::OrderExpired } else { EscrowEvent::OrderCancelled };
+        let event = Self::order_event(maker_order, unfilled_amount, event);
+
+        let token = maker_order.token.clone();
+        let maker = maker_order.maker.clone();
+        // refunds of NEAR orders stay NEAR, only a wNEAR order's own contract matters here
+        let wrap_account = Self::order_wrap_account(maker_order, None);
+        let refund_asset = Self::payout_asset(&token, wrap_account.as_ref(), &EscrowStatus::Cancelled);
+
+        NearWrapper::transfer(self, &token, &refund_asset, order_root_hash.clone(), maker, unfilled_amount)
+            .then(
+                ext_escrow::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_CANCEL_ORDER)
+                    .on_cancel_order_resolved(order_root_hash, unfilled_amount, event)
+            )
+    }
+
+    /**
+     * @dev Same as cancel_maker_order, kept for makers reclaiming once MakerOrder.expiration
+     * has passed.
+     */
+    pub fn reclaim_expired_order(&mut self, order_root_hash: String) -> Promise {
+        let maker_order = self.makers_orders.get(&order_root_hash)
+            .expect("Order doesn't exist...");
+        require!(env::block_timestamp() > maker_order.expiration, "Order has not expired yet...");
+
+        self.cancel_maker_order(order_root_hash)
+    }
+
+    // Finalizes a maker cancellation once the token transfer has settled and emits its event.
+    // On failure the order is re-opened so the maker can retry, a refund that was already
+    // converted is converted back into the order's token first.
+    #[private]
+    pub fn on_cancel_order_resolved(
+        &mut self,
+        order_root_hash: String,
+        amount: NearToken,
+        event: EscrowEvent,
+        #[callback_result] transfer_result: Result<(), PromiseError>
+    ) -> bool {
+        let converted_asset = self.converted_refunds.remove(&order_root_hash);
+        let maker_order = self.makers_orders.get_mut(&order_root_hash)
+            .expect("Order doesn't exist...");
+
+        if transfer_result.is_err() {
+            log!("Refund of {} failed, re-opening order {}", amount, order_root_hash);
+            if let Some(converted_asset) = converted_asset {
+                NearWrapper::restore(&converted_asset, &maker_order.token, amount)
+                    .unwrap_or_else(|| env::panic_str("Unsupported asset conversion..."))
+                    .detach();
+            }
+            maker_order.cancelled_amount = ZERO_NEAR;
+            return false;
+        }
+
+        event.emit();
+
+        // every fill is already settled, the order is done
+        if Self::is_order_closed(maker_order) {
+            self.remove_maker_order(&order_root_hash);
+        }
+
+        true
     }
 
     // a simple method to check existance of order based on immutables
@@ -303,9 +590,176 @@ impl EscrowSrc {
 
 
 
+// block of internal functions
+impl EscrowSrc {
+    // Places the hex-encoded borsh MakerOrder of a token transfer (ft_on_transfer, mt_on_transfer),
+    // returns the amount to refund to the sender
+    fn place_transferred_order(&mut self, sender_id: &AccountId, token: Asset, amount: NearToken, msg: String) -> NearToken {
+        // Validate hex string
+        let bytes_hex = hex::decode(msg);
+        if bytes_hex.is_err() {
+            log!("Invalid hex string provided");
+            return amount;
+        }
+
+        // Deserialize the maker order from the hex string
+        // This will fail if the data is not a valid MakerOrder
+        let maker_order = MakerOrder::try_from_slice(&bytes_hex.unwrap());
+        if maker_order.is_err() {
+            log!("Invalid maker order data");
+            return amount;
+        }
+
+        let maker_order = maker_order.unwrap();
+
+        require!(maker_order.maker == *sender_id, "Maker order does not match the sender ID");
+        require!(maker_order.total_amount <= amount, "Maker order total amount is greater than the transferred amount");
+        require!(maker_order.token == token, "Invalid token");
+        self.validate_new_maker_order(&maker_order);
+
+        // Return unused tokens if any
+        let unused_tokens = amount.checked_sub(maker_order.total_amount);
+
+        if let Some(unused_tokens) = unused_tokens.filter(|unused| *unused > ZERO_NEAR) {
+            log!("Unused tokens detected: {}", unused_tokens);
+            return unused_tokens;
+        }
+
+        // Store the maker order in the lookup map
+        self.store_maker_order(maker_order);
+
+        ZERO_NEAR
+    }
+
+    // Stores a validated maker order together with its index entries
+    fn store_maker_order(&mut self, maker_order: MakerOrder) {
+        Self::order_event(&maker_order, maker_order.total_amount, EscrowEvent::OrderCreated).emit();
+        self.orders_by_maker.insert(&maker_order.maker, maker_order.root_hash.clone());
+        self.orders_by_token.insert(&maker_order.token, maker_order.root_hash.clone());
+        self.makers_orders.insert(maker_order.root_hash.clone(), maker_order);
+    }
+
+    fn remove_maker_order(&mut self, order_root_hash: &str) {
+        if let Some(maker_order) = self.makers_orders.remove(order_root_hash) {
+            self.orders_by_maker.remove(&maker_order.maker, order_root_hash);
+            self.orders_by_token.remove(&maker_order.token, order_root_hash);
+        }
+    }
+
+    // Checks shared by every way of placing a maker order
+    fn validate_new_maker_order(&self, maker_order: &MakerOrder) {
+        require!(!self.makers_orders.contains_key(&maker_order.root_hash), "Maker order already exists");
+        require!(maker_order.filled_amount == ZERO_NEAR, "Maker order is already filled");
+        require!(maker_order.withdrawn_amount == ZERO_NEAR, "Maker order is already withdrawn");
+        require!(maker_order.cancelled_amount == ZERO_NEAR, "Maker order is already cancelled");
+        require!(maker_order.token.is_local(), "Maker order must sell an asset of this chain");
+        require!(maker_order.expiration > env::block_timestamp() + 500, "Maker order has expired");
+        require!(maker_order.min_taking_amount > ZERO_NEAR, "Maker order must ask for a taking amount");
+        require!(maker_order.auction.as_ref().is_none_or(|auction| auction.is_valid()), "Invalid auction details");
+        require!(self.is_current_epoch(maker_order), "Maker order epoch is not current");
+        require!(
+            !maker_order.convert_near || maker_order.token == Asset::Near || self.is_wrapped_near(&maker_order.token),
+            "Only NEAR and wNEAR orders can convert NEAR"
+        );
+
+        // an NFT is indivisible, it is sold whole by a single fill
+        if let Asset::Nft { .. } = maker_order.token {
+            require!(maker_order.total_amount == NFT_AMOUNT && maker_order.parts == 1, "NFT orders must be a single part of amount 1");
+        }
+    }
+
+    // Asset an order's escrowed token is paid out as, given the wNEAR contract pinned for it:
+    // resolvers of a convert_near NEAR order receive wNEAR, makers of a convert_near wNEAR order
+    // get their refunds as NEAR
+    fn payout_asset(held_asset: &Asset, wrap_account: Option<&AccountId>, status: &EscrowStatus) -> Asset {
+        match (wrap_account, status) {
+            (Some(wrap_account), EscrowStatus::Withdrawn) if *held_asset == Asset::Near => Asset::Ft(wrap_account.clone()),
+            (Some(wrap_account), EscrowStatus::Cancelled) if *held_asset == Asset::Ft(wrap_account.clone()) => Asset::Near,
+            _ => held_asset.clone()
+        }
+    }
+
+    // wNEAR contract a convert_near order converts through: a wNEAR order's own token (checked
+    // when it was placed), or the configured contract for a NEAR order. None without convert_near.
+    fn order_wrap_account(maker_order: &MakerOrder, configured: Option<&AccountId>) -> Option<AccountId> {
+        match (&maker_order.token, maker_order.convert_near) {
+            (_, false) => None,
+            (Asset::Ft(wrap_account), true) => Some(wrap_account.clone()),
+            (_, true) => configured.cloned()
+        }
+    }
+
+    // Timelock of a placed escrow, anchored at the time it was created
+    fn escrow_timelock(&self, immutables: &Immutables) -> TimeLock {
+        self.resolver_orders.get(&immutables.hash())
+            .expect("Escrow doesn't exist...")
+            .immutables.timelock.clone()
+    }
+
+    // Moves an active escrow into its final status and pays out its making amount
+    // (native NEAR or fungible token, see transfer_asset), wrapped or unwrapped for
+    // convert_near orders. A conversion that succeeded before a failed payout is kept as
+    // the fill's held asset, so a retry pays it out without converting again.
+    // The entry stays in storage until on_settlement_resolved sees the transfer result,
+    // which blocks any second settlement of the same escrow in the meantime.
+    fn settle_escrow(&mut self, immutables: &Immutables, status: EscrowStatus, receiver_id: AccountId, slash_stake: bool, event: EscrowEvent) -> Promise {
+        let immutables_hash = immutables.hash();
+        let fill = self.resolver_orders.get_mut(&immutables_hash)
+            .expect("Escrow doesn't exist...");
+        let payout_asset = Self::payout_asset(&immutables.making_token, fill.wrap_account.as_ref(), &status);
+        let held_asset = fill.held_asset.clone();
+        require!(fill.status == EscrowStatus::Active, "Escrow already settled...");
+        fill.status = status;
+
+        NearWrapper::transfer(self, &held_asset, &payout_asset, immutables_hash.clone(), receiver_id, immutables.making_amount)
+            .then(
+                ext_escrow::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_SETTLEMENT)
+                    .on_settlement_resolved(immutables_hash, env::predecessor_account_id(), slash_stake, event)
+            )
+    }
+}
+
 // block of static functions
 #[near_bindgen]
 impl EscrowSrc  {
+    fn order_event(maker_order: &MakerOrder, amount: NearToken, event: fn(OrderEvent) -> EscrowEvent) -> EscrowEvent {
+        event(OrderEvent {
+            order_root_hash: maker_order.root_hash.clone(),
+            maker: maker_order.maker.clone(),
+            token: maker_order.token.clone(),
+            amount
+        })
+    }
+
+    fn withdrawn_event(immutables: &Immutables, secret: String, receiver: AccountId) -> EscrowEvent {
+        EscrowEvent::Withdrawn(WithdrawnEvent {
+            immutables_hash: immutables.hash(),
+            hashlock: immutables.hashlock.clone(),
+            secret,
+            receiver,
+            token: immutables.making_token.clone(),
+            amount: immutables.making_amount,
+            caller: env::predecessor_account_id()
+        })
+    }
+
+    fn cancelled_event(immutables: &Immutables, receiver: AccountId) -> CancelledEvent {
+        CancelledEvent {
+            immutables_hash: immutables.hash(),
+            receiver,
+            token: immutables.making_token.clone(),
+            amount: immutables.making_amount,
+            caller: env::predecessor_account_id()
+        }
+    }
+
+    // Every token of the order is either settled through a fill or refunded to the maker
+    fn is_order_closed(maker_order: &MakerOrder) -> bool {
+        maker_order.withdrawn_amount.checked_add(maker_order.cancelled_amount)
+            .is_some_and(|released| released == maker_order.total_amount)
+    }
+
     fn completes_last_partial_fill(
         total_amount: &NearToken,
         filled_amount: &NearToken,
@@ -328,6 +782,23 @@ impl EscrowSrc  {
         making_amount_u128 > remaning
     }
     
+    // Pro-rata share of the order's minimum taking amount for a making amount,
+    // rounded up so partial fills never undercut the maker's price
+    fn min_taking_amount(
+        order_min_taking_amount: &NearToken,
+        total_amount: &NearToken,
+        making_amount: &NearToken
+    ) -> NearToken {
+        let min_taking_amount = shared_lib::utils::mul_div(
+            order_min_taking_amount.as_yoctonear(),
+            making_amount.as_yoctonear(),
+            total_amount.as_yoctonear(),
+            true
+        ).expect("Overflow when calculating minimum taking amount");
+
+        NearToken::from_yoctonear(min_taking_amount)
+    }
+
     // Compute valid index for a fill
     fn compute_valid_index(
         total_amount: &NearToken,
@@ -365,3 +836,241 @@ impl EscrowSrc  {
         index as u16
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{mock::MockAction, test_utils::{get_created_receipts, VMContextBuilder}, testing_env};
+
+    use super::*;
+
+    const ESCROW: &str = "escrow.near";
+    const MAKER: &str = "maker.near";
+    const RESOLVER: &str = "resolver.near";
+    const WRAP: &str = "wrap.near";
+    const ROOT_HASH: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+
+    fn near(yocto: u128) -> NearToken {
+        NearToken::from_yoctonear(yocto)
+    }
+
+    fn account(name: &str) -> AccountId {
+        name.parse().unwrap()
+    }
+
+    fn set_caller(caller: &str) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account(ESCROW))
+            .predecessor_account_id(account(caller))
+            .build());
+    }
+
+    fn wnear() -> Asset {
+        Asset::Ft(account(WRAP))
+    }
+
+    fn order(token: Asset, convert_near: bool) -> MakerOrder {
+        MakerOrder {
+            root_hash: ROOT_HASH.to_string(),
+            token,
+            total_amount: NearToken::from_near(1),
+            parts: 1,
+            filled_amount: ZERO_NEAR,
+            withdrawn_amount: ZERO_NEAR,
+            cancelled_amount: ZERO_NEAR,
+            maker: account(MAKER),
+            expiration: 1_000,
+            taking_token: Asset::Foreign("0xtoken".to_string()),
+            receiver: "0xreceiver".to_string(),
+            min_taking_amount: NearToken::from_near(1),
+            auction: None,
+            epoch: 0,
+            exclusive_resolver: None,
+            convert_near
+        }
+    }
+
+    fn immutables(making_token: Asset) -> Immutables {
+        Immutables {
+            salt: "salt".to_string(),
+            order_root_hash: ROOT_HASH.to_string(),
+            hashlock: ROOT_HASH.to_string(),
+            making_token,
+            taking_token: Asset::Foreign("0xtoken".to_string()),
+            making_amount: NearToken::from_near(1),
+            taking_amount: NearToken::from_near(1),
+            src_safty_deposit: ZERO_NEAR,
+            dst_safty_deposit: ZERO_NEAR,
+            timelock: TimeLock {
+                deployed_at: 0,
+                src_withdrawal: 60,
+                src_public_withdrawal: 120,
+                src_cancellation: 180,
+                src_public_cancellation: 240,
+                dst_withdrawal: 60,
+                dst_public_withdrawal: 120,
+                dst_cancellation: 150,
+                dst_public_cancellation: 210
+            },
+            maker: MAKER.to_string(),
+            taker: RESOLVER.to_string(),
+            receiver: "0xreceiver".to_string()
+        }
+    }
+
+    // contract with `order` fully filled by RESOLVER through an escrow of `immutables`
+    fn filled_contract(mut order: MakerOrder, immutables: &Immutables, wrap_account: Option<AccountId>) -> EscrowSrc {
+        set_caller(RESOLVER);
+        let mut contract = EscrowSrc::default();
+        order.filled_amount = immutables.making_amount;
+        contract.store_maker_order(order);
+        contract.fills_by_taker.insert(&account(RESOLVER), immutables.hash());
+        contract.resolver_orders.insert(immutables.hash(), ResolverOrderFill {
+            immutables: immutables.clone(),
+            status: EscrowStatus::Active,
+            locked_stake: ZERO_NEAR,
+            wrap_account,
+            held_asset: immutables.making_token.clone()
+        });
+        contract
+    }
+
+    fn called_methods() -> Vec<String> {
+        get_created_receipts().into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                MockAction::FunctionCallWeight { method_name, .. } => Some(String::from_utf8(method_name).unwrap()),
+                _ => None
+            })
+            .collect()
+    }
+
+    fn withdrawn(immutables: &Immutables) -> EscrowEvent {
+        EscrowSrc::withdrawn_event(immutables, "secret".to_string(), account(RESOLVER))
+    }
+
+    fn withdraw(contract: &mut EscrowSrc, immutables: &Immutables) {
+        contract.settle_escrow(immutables, EscrowStatus::Withdrawn, account(RESOLVER), false, withdrawn(immutables)).detach();
+    }
+
+    #[test]
+    fn failed_payout_keeps_the_converted_asset() {
+        let immutables = immutables(Asset::Near);
+        let immutables_hash = immutables.hash();
+        let mut contract = filled_contract(order(Asset::Near, true), &immutables, Some(account(WRAP)));
+
+        // NEAR is wrapped, then paying out the wNEAR fails
+        withdraw(&mut contract, &immutables);
+        assert!(called_methods().contains(&"near_deposit".to_string()));
+        set_caller(ESCROW);
+        contract.on_near_wrapped(immutables_hash.clone(), account(WRAP), account(RESOLVER), immutables.making_amount, Ok(())).detach();
+        assert!(!contract.on_settlement_resolved(immutables_hash.clone(), account(RESOLVER), false, withdrawn(&immutables), Err(PromiseError::Failed)));
+
+        let fill = contract.resolver_orders.get(&immutables_hash).unwrap();
+        assert_eq!(fill.status, EscrowStatus::Active);
+        assert_eq!(fill.held_asset, wnear());
+
+        // the retry pays out the wNEAR the fill now holds, nothing is wrapped twice
+        set_caller(ESCROW);
+        withdraw(&mut contract, &immutables);
+        let methods = called_methods();
+        assert!(!methods.contains(&"near_deposit".to_string()));
+        assert!(methods.contains(&"storage_balance_of".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow already settled...")]
+    fn escrow_settles_once() {
+        let immutables = immutables(Asset::Near);
+        let mut contract = filled_contract(order(Asset::Near, false), &immutables, None);
+        withdraw(&mut contract, &immutables);
+        withdraw(&mut contract, &immutables);
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow doesn't exist...")]
+    fn missing_escrow_can_not_be_settled() {
+        let immutables = immutables(Asset::Near);
+        let mut contract = filled_contract(order(Asset::Near, false), &immutables, None);
+        let mut other = immutables.clone();
+        other.salt = "other salt".to_string();
+        withdraw(&mut contract, &other);
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow doesn't exist...")]
+    fn missing_escrow_can_not_be_resolved() {
+        set_caller(ESCROW);
+        let immutables = immutables(Asset::Near);
+        EscrowSrc::default().on_settlement_resolved(immutables.hash(), account(RESOLVER), false, withdrawn(&immutables), Ok(()));
+    }
+
+    #[test]
+    fn failed_settlement_reactivates_the_escrow() {
+        let immutables = immutables(Asset::Near);
+        let immutables_hash = immutables.hash();
+        let mut contract = filled_contract(order(Asset::Near, false), &immutables, None);
+        withdraw(&mut contract, &immutables);
+        assert_eq!(contract.resolver_orders.get(&immutables_hash).unwrap().status, EscrowStatus::Withdrawn);
+
+        set_caller(ESCROW);
+        assert!(!contract.on_settlement_resolved(immutables_hash.clone(), account(RESOLVER), false, withdrawn(&immutables), Err(PromiseError::Failed)));
+        assert_eq!(contract.resolver_orders.get(&immutables_hash).unwrap().status, EscrowStatus::Active);
+        assert!(contract.makers_orders.contains_key(ROOT_HASH));
+
+        // settled again, this time for good: the fill and the fully withdrawn order are gone
+        withdraw(&mut contract, &immutables);
+        assert!(contract.on_settlement_resolved(immutables_hash.clone(), account(RESOLVER), false, withdrawn(&immutables), Ok(())));
+        assert!(contract.resolver_orders.get(&immutables_hash).is_none());
+        assert!(!contract.makers_orders.contains_key(ROOT_HASH));
+        assert!(contract.fills_by_taker.iter(&account(RESOLVER)).next().is_none());
+    }
+
+    #[test]
+    fn failed_refund_converts_back() {
+        set_caller(MAKER);
+        let mut contract = EscrowSrc::default();
+        contract.store_maker_order(order(wnear(), true));
+
+        // wNEAR is unwrapped for the maker, then sending the NEAR fails
+        contract.cancel_maker_order(ROOT_HASH.to_string()).detach();
+        set_caller(ESCROW);
+        let amount = NearToken::from_near(1);
+        contract.on_near_unwrapped(ROOT_HASH.to_string(), account(WRAP), account(MAKER), amount, Ok(())).detach();
+        assert_eq!(contract.converted_refunds.get(ROOT_HASH), Some(&Asset::Near));
+
+        let event = EscrowSrc::order_event(contract.makers_orders.get(ROOT_HASH).unwrap(), amount, EscrowEvent::OrderCancelled);
+        assert!(!contract.on_cancel_order_resolved(ROOT_HASH.to_string(), amount, event, Err(PromiseError::Failed)));
+
+        // the NEAR is wrapped again and the order is open as before
+        assert!(called_methods().contains(&"near_deposit".to_string()));
+        assert!(contract.converted_refunds.get(ROOT_HASH).is_none());
+        assert_eq!(contract.makers_orders.get(ROOT_HASH).unwrap().cancelled_amount, ZERO_NEAR);
+    }
+
+    #[test]
+    fn min_taking_amount_is_proportional_to_the_fill() {
+        assert_eq!(EscrowSrc::min_taking_amount(&near(1_000), &near(100), &near(100)), near(1_000));
+        assert_eq!(EscrowSrc::min_taking_amount(&near(1_000), &near(100), &near(25)), near(250));
+    }
+
+    #[test]
+    fn min_taking_amount_rounds_in_the_makers_favour() {
+        // 1_000 * 1 / 3 = 333.33.. asks for 334
+        assert_eq!(EscrowSrc::min_taking_amount(&near(1_000), &near(3), &near(1)), near(334));
+        // even a tiny fill of a huge order pays something
+        assert_eq!(EscrowSrc::min_taking_amount(&near(1), &near(u128::MAX), &near(1)), near(1));
+    }
+
+    #[test]
+    fn min_taking_amount_handles_large_amounts() {
+        // the intermediate product overflows u128
+        let total = near(u128::MAX / 2);
+        assert_eq!(EscrowSrc::min_taking_amount(&near(u128::MAX), &total, &total), near(u128::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow when calculating minimum taking amount")]
+    fn min_taking_amount_panics_on_overflow() {
+        EscrowSrc::min_taking_amount(&near(u128::MAX), &near(1), &near(2));
+    }
+}
@@ -0,0 +1,321 @@
+use shared_lib::legacy::LegacyImmutables;
+
+use crate::*;
+
+// Prefixes of the first deployed layout, its entries stay readable there (see rescue_legacy_*)
+pub(crate) const LEGACY_MAKERS_ORDERS_PREFIX: &[u8] = b"m";
+pub(crate) const LEGACY_RESOLVER_ORDERS_PREFIX: &[u8] = b"r";
+
+const GAS_FOR_RESOLVE_LEGACY_RESCUE: Gas = Gas::from_tgas(10);
+
+#[ext_contract(ext_legacy)]
+trait _LegacyCallbacks {
+    fn on_legacy_order_rescued(&mut self, root_hash: String, order: LegacyMakerOrder) -> bool;
+
+    fn on_legacy_fill_rescued(&mut self, immutables_hash: String, fill: LegacyResolverOrderFill) -> bool;
+}
+
+// First deployed layout: two LookupMaps, whose borsh encoding is just their prefix
+#[derive(BorshDeserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+struct LegacyEscrowSrc {
+    makers_orders: Vec<u8>,
+    resolver_orders: Vec<u8>
+}
+
+// Maker order of the first deployed layout
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct LegacyMakerOrder {
+    pub root_hash: String,
+    pub token: AccountId,
+    pub total_amount: NearToken,
+    pub parts: u16,
+    pub filled_amount: NearToken,
+    pub withdrawn_amount: NearToken,
+    pub maker: AccountId,
+    pub expiration: u64
+}
+
+// Resolver fill of the first deployed layout, never removed once settled back then
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct LegacyResolverOrderFill {
+    pub immutables: LegacyImmutables
+}
+
+#[near_bindgen]
+impl EscrowSrc {
+    // Upgrades the first deployed layout to the current one (deploy the new code, then call this)
+    // LookupMap entries can't be enumerated and miss fields the current orders need, so orders
+    // and fills of the old layout stay where they are, readable through legacy_makers_orders
+    // and legacy_resolver_orders, and are paid out with rescue_legacy_order / rescue_legacy_fill.
+    // The current maps use other prefixes, so they never alias entries of the old layout.
+    // Panics on any other state, which also keeps the migration from running twice.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let legacy: LegacyEscrowSrc = env::state_read().expect("Contract state doesn't exist...");
+        require!(
+            legacy.makers_orders == LEGACY_MAKERS_ORDERS_PREFIX && legacy.resolver_orders == LEGACY_RESOLVER_ORDERS_PREFIX,
+            "Unknown contract state layout..."
+        );
+
+        log!("Migrated escrow state to the current layout");
+        Self::default()
+    }
+
+    pub fn get_legacy_order(&self, root_hash: String) -> Option<LegacyMakerOrder> {
+        self.legacy_makers_orders.get(&root_hash).cloned()
+    }
+
+    pub fn get_legacy_fill(&self, immutables_hash: String) -> Option<LegacyResolverOrderFill> {
+        self.legacy_resolver_orders.get(&immutables_hash).cloned()
+    }
+
+    // Maker takes back the unfilled remainder of an order placed before the upgrade
+    // Filled parts are locked in legacy fills and paid out by rescue_legacy_fill
+    pub fn rescue_legacy_order(&mut self, root_hash: String) -> Promise {
+        let order = self.legacy_makers_orders.remove(&root_hash)
+            .expect("Order doesn't exist...");
+        require!(env::predecessor_account_id() == order.maker, "Only maker can rescue...");
+
+        let unfilled_amount = order.total_amount.checked_sub(order.filled_amount)
+            .expect("Overflow when calculating unfilled amount");
+        require!(unfilled_amount > ZERO_NEAR, "Nothing to rescue...");
+
+        self.safe_ft_transfer(order.token.clone(), order.maker.clone(), unfilled_amount)
+            .then(
+                ext_legacy::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_LEGACY_RESCUE)
+                    .on_legacy_order_rescued(root_hash, order)
+            )
+    }
+
+    // Owner pays out a fill placed before the upgrade: its making amount to `receiver_id`,
+    // its maker or its taker depending on how the swap went, and its safety deposit back to
+    // the taker. The first layout kept settled fills around, so the owner drops those by
+    // passing no receiver.
+    pub fn rescue_legacy_fill(&mut self, immutables_hash: String, receiver_id: Option<AccountId>) -> PromiseOrValue<bool> {
+        self.resolver_registry.assert_owner();
+        let fill = self.legacy_resolver_orders.remove(&immutables_hash)
+            .expect("Escrow doesn't exist...");
+
+        let Some(receiver_id) = receiver_id else {
+            log!("Dropped settled legacy escrow {}", immutables_hash);
+            return PromiseOrValue::Value(true);
+        };
+        fill.immutables.assert_party(&receiver_id);
+
+        let token = AccountId::from_str(&fill.immutables.making_token).expect("Invalid token...");
+        let amount = fill.immutables.making_amount;
+        PromiseOrValue::Promise(
+            self.safe_ft_transfer(token, receiver_id, amount)
+                .then(
+                    ext_legacy::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_LEGACY_RESCUE)
+                        .on_legacy_fill_rescued(immutables_hash, fill)
+                )
+        )
+    }
+
+    // Puts the order back if its refund failed, so the maker can retry
+    #[private]
+    pub fn on_legacy_order_rescued(
+        &mut self,
+        root_hash: String,
+        order: LegacyMakerOrder,
+        #[callback_result] transfer_result: Result<(), PromiseError>
+    ) -> bool {
+        if transfer_result.is_err() {
+            log!("Rescue of legacy order {} failed", root_hash);
+            self.legacy_makers_orders.insert(root_hash, order);
+            return false;
+        }
+
+        true
+    }
+
+    // Returns the safety deposit once the payout went through, puts the fill back otherwise
+    #[private]
+    pub fn on_legacy_fill_rescued(
+        &mut self,
+        immutables_hash: String,
+        fill: LegacyResolverOrderFill,
+        #[callback_result] transfer_result: Result<(), PromiseError>
+    ) -> bool {
+        if transfer_result.is_err() {
+            log!("Rescue of legacy escrow {} failed", immutables_hash);
+            self.legacy_resolver_orders.insert(immutables_hash, fill);
+            return false;
+        }
+
+        if fill.immutables.src_safty_deposit > ZERO_NEAR {
+            Promise::new(fill.immutables.taker()).transfer(fill.immutables.src_safty_deposit).detach();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+    use shared_lib::legacy::LegacyTimeLock;
+
+    use super::*;
+
+    #[derive(BorshSerialize)]
+    #[borsh(crate = "near_sdk::borsh")]
+    struct LegacyState {
+        makers_orders: Vec<u8>,
+        resolver_orders: Vec<u8>
+    }
+
+    fn set_escrow() {
+        set_caller("escrow.near");
+    }
+
+    fn set_caller(caller: &str) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("escrow.near".parse().unwrap())
+            .predecessor_account_id(caller.parse().unwrap())
+            .build());
+    }
+
+    fn legacy_order() -> LegacyMakerOrder {
+        LegacyMakerOrder {
+            root_hash: "root".to_string(),
+            token: "token.near".parse().unwrap(),
+            total_amount: NearToken::from_yoctonear(1_000),
+            parts: 1,
+            filled_amount: NearToken::from_yoctonear(400),
+            withdrawn_amount: ZERO_NEAR,
+            maker: "maker.near".parse().unwrap(),
+            expiration: 1_000
+        }
+    }
+
+    fn legacy_fill() -> LegacyResolverOrderFill {
+        LegacyResolverOrderFill {
+            immutables: LegacyImmutables {
+                salt: "salt".to_string(),
+                order_root_hash: "root".to_string(),
+                hashlock: "root".to_string(),
+                making_token: "token.near".to_string(),
+                taking_token: "0xtoken".to_string(),
+                making_amount: NearToken::from_yoctonear(400),
+                taking_amount: NearToken::from_yoctonear(400),
+                src_safty_deposit: NearToken::from_yoctonear(10),
+                dst_safty_deposit: NearToken::from_yoctonear(10),
+                timelock: LegacyTimeLock {
+                    src_withdrawal: 1,
+                    src_public_withdrawal: 2,
+                    src_cancellation: 3,
+                    src_public_cancellation: 4,
+                    dst_withdrawal: 1,
+                    dst_public_withdrawal: 2,
+                    dst_cancellation: 3
+                },
+                maker: "maker.near".to_string(),
+                taker: "resolver.near".to_string()
+            }
+        }
+    }
+
+    // legacy state with one order and one fill of it, written as the first layout did
+    fn migrated_contract() -> EscrowSrc {
+        set_escrow();
+        env::state_write(&LegacyState { makers_orders: b"m".to_vec(), resolver_orders: b"r".to_vec() });
+        let mut orders = LookupMap::new(LEGACY_MAKERS_ORDERS_PREFIX);
+        orders.insert("root".to_string(), legacy_order());
+        orders.flush();
+        let mut fills = LookupMap::new(LEGACY_RESOLVER_ORDERS_PREFIX);
+        fills.insert("fill".to_string(), legacy_fill());
+        fills.flush();
+
+        EscrowSrc::migrate()
+    }
+
+    #[test]
+    fn migrates_the_first_layout() {
+        set_escrow();
+        env::state_write(&LegacyState { makers_orders: b"m".to_vec(), resolver_orders: b"r".to_vec() });
+
+        let contract = EscrowSrc::migrate();
+        assert_eq!(contract.resolver_registry.owner().as_str(), "escrow.near");
+        assert_eq!(contract.slash_bps, DEFAULT_SLASH_BPS);
+    }
+
+    #[test]
+    fn legacy_entries_stay_readable() {
+        let contract = migrated_contract();
+        assert_eq!(contract.get_legacy_order("root".to_string()).unwrap().filled_amount.as_yoctonear(), 400);
+        assert_eq!(contract.get_legacy_fill("fill".to_string()).unwrap().immutables.taker, "resolver.near");
+    }
+
+    #[test]
+    fn maker_rescues_the_unfilled_remainder() {
+        let mut contract = migrated_contract();
+        set_caller("maker.near");
+        contract.rescue_legacy_order("root".to_string()).detach();
+        assert!(contract.get_legacy_order("root".to_string()).is_none());
+
+        // a failed refund puts the order back for another try
+        set_escrow();
+        assert!(!contract.on_legacy_order_rescued("root".to_string(), legacy_order(), Err(PromiseError::Failed)));
+        assert!(contract.get_legacy_order("root".to_string()).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only maker can rescue...")]
+    fn only_maker_rescues_an_order() {
+        let mut contract = migrated_contract();
+        set_caller("resolver.near");
+        contract.rescue_legacy_order("root".to_string()).detach();
+    }
+
+    #[test]
+    fn owner_pays_out_or_drops_legacy_fills() {
+        let mut contract = migrated_contract();
+        assert!(matches!(contract.rescue_legacy_fill("fill".to_string(), Some("resolver.near".parse().unwrap())), PromiseOrValue::Promise(_)));
+        assert!(contract.get_legacy_fill("fill".to_string()).is_none());
+
+        // a failed payout puts the fill back, a settled one is just dropped
+        assert!(!contract.on_legacy_fill_rescued("fill".to_string(), legacy_fill(), Err(PromiseError::Failed)));
+        assert!(matches!(contract.rescue_legacy_fill("fill".to_string(), None), PromiseOrValue::Value(true)));
+        assert!(contract.get_legacy_fill("fill".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Receiver must be the maker or the taker...")]
+    fn legacy_fills_only_pay_their_parties() {
+        let mut contract = migrated_contract();
+        contract.rescue_legacy_fill("fill".to_string(), Some("owner.near".parse().unwrap())).detach();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can manage resolvers...")]
+    fn only_owner_rescues_a_fill() {
+        let mut contract = migrated_contract();
+        set_caller("resolver.near");
+        contract.rescue_legacy_fill("fill".to_string(), None).detach();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown contract state layout...")]
+    fn rejects_other_prefixes() {
+        set_escrow();
+        env::state_write(&LegacyState { makers_orders: b"a".to_vec(), resolver_orders: b"c".to_vec() });
+        EscrowSrc::migrate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot deserialize the contract state.")]
+    fn can_not_migrate_twice() {
+        set_escrow();
+        env::state_write(&EscrowSrc::default());
+        EscrowSrc::migrate();
+    }
+}
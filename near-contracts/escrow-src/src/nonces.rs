@@ -0,0 +1,250 @@
+use crate::*;
+
+// Maker side order invalidation:
+// - every signed intent nonce can be used once (bit invalidator, 128 nonces per slot)
+// - invalidate_nonces_below kills all intents with a lower nonce
+// - increase_epoch kills every order placed or signed under the previous epoch
+// The caller pays for the storage these add (see shared_lib::storage), extra deposit is refunded
+#[near_bindgen]
+impl EscrowSrc {
+    // Marks a single intent nonce of the caller as used
+    #[payable]
+    pub fn invalidate_nonce(&mut self, nonce: u64) {
+        let initial_usage = env::storage_usage();
+        let maker = env::predecessor_account_id();
+        let (key, bit) = Self::nonce_slot(&maker, nonce);
+        let slot = self.nonce_bitmaps.get(&key).copied().unwrap_or(0);
+        self.nonce_bitmaps.insert(key, slot | bit);
+
+        self.nonce_bitmaps.flush();
+        shared_lib::storage::settle_storage(initial_usage);
+    }
+
+    // Invalidates every intent of the caller with a nonce below `nonce`
+    #[payable]
+    pub fn invalidate_nonces_below(&mut self, nonce: u64) {
+        let initial_usage = env::storage_usage();
+        let maker = env::predecessor_account_id();
+        require!(nonce > self.get_min_nonce(maker.clone()), "Nonces can only be invalidated upwards...");
+        self.min_nonces.insert(maker, nonce);
+
+        self.min_nonces.flush();
+        shared_lib::storage::settle_storage(initial_usage);
+    }
+
+    // Invalidates every order of the caller placed or signed under the current epoch,
+    // orders already being filled can't be filled any further
+    #[payable]
+    pub fn increase_epoch(&mut self) -> u64 {
+        let initial_usage = env::storage_usage();
+        let maker = env::predecessor_account_id();
+        let epoch = self.get_maker_epoch(maker.clone()) + 1;
+        self.maker_epochs.insert(maker, epoch);
+
+        self.maker_epochs.flush();
+        shared_lib::storage::settle_storage(initial_usage);
+        epoch
+    }
+
+    pub fn get_maker_epoch(&self, maker: AccountId) -> u64 {
+        self.maker_epochs.get(&maker).copied().unwrap_or(0)
+    }
+
+    pub fn get_min_nonce(&self, maker: AccountId) -> u64 {
+        self.min_nonces.get(&maker).copied().unwrap_or(0)
+    }
+
+    pub fn is_nonce_used(&self, maker: AccountId, nonce: u64) -> bool {
+        if nonce < self.get_min_nonce(maker.clone()) {
+            return true;
+        }
+
+        let (key, bit) = Self::nonce_slot(&maker, nonce);
+        self.nonce_bitmaps.get(&key).is_some_and(|slot| slot & bit != 0)
+    }
+}
+
+impl EscrowSrc {
+    // Marks a maker nonce as used, panics if it was already used or invalidated
+    pub(crate) fn use_nonce(&mut self, maker: &AccountId, nonce: u64) {
+        require!(!self.is_nonce_used(maker.clone(), nonce), "Nonce already used...");
+
+        let (key, bit) = Self::nonce_slot(maker, nonce);
+        let slot = self.nonce_bitmaps.get(&key).copied().unwrap_or(0);
+        self.nonce_bitmaps.insert(key, slot | bit);
+    }
+
+    pub(crate) fn is_current_epoch(&self, maker_order: &MakerOrder) -> bool {
+        maker_order.epoch == self.get_maker_epoch(maker_order.maker.clone())
+    }
+
+    fn nonce_slot(maker: &AccountId, nonce: u64) -> ((AccountId, u64), u128) {
+        ((maker.clone(), nonce / 128), 1u128 << (nonce % 128))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{mock::MockAction, test_utils::{get_created_receipts, VMContextBuilder}, testing_env};
+
+    use super::*;
+
+    fn account(name: &str) -> AccountId {
+        name.parse().unwrap()
+    }
+
+    // caller attaching enough for the storage of any invalidation
+    fn set_caller(caller: &str) {
+        set_caller_with_deposit(caller, NearToken::from_near(1));
+    }
+
+    fn set_caller_with_deposit(caller: &str, deposit: NearToken) {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(account(caller)).attached_deposit(deposit).build());
+    }
+
+    fn refunds() -> Vec<NearToken> {
+        get_created_receipts().into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                MockAction::Transfer { deposit, .. } => Some(deposit),
+                _ => None
+            })
+            .collect()
+    }
+
+    fn order(maker: &str, epoch: u64) -> MakerOrder {
+        MakerOrder {
+            root_hash: "hashlock".to_string(),
+            token: Asset::Near,
+            total_amount: NearToken::from_yoctonear(1_000),
+            parts: 1,
+            filled_amount: ZERO_NEAR,
+            withdrawn_amount: ZERO_NEAR,
+            cancelled_amount: ZERO_NEAR,
+            maker: account(maker),
+            expiration: 1_000,
+            taking_token: Asset::Foreign("0xtoken".to_string()),
+            receiver: "0xreceiver".to_string(),
+            min_taking_amount: NearToken::from_yoctonear(900),
+            auction: None,
+            epoch,
+            exclusive_resolver: None,
+            convert_near: false
+        }
+    }
+
+    #[test]
+    fn nonces_map_to_slot_bits() {
+        let maker = account("maker.near");
+        assert_eq!(EscrowSrc::nonce_slot(&maker, 0), ((maker.clone(), 0), 1));
+        assert_eq!(EscrowSrc::nonce_slot(&maker, 127), ((maker.clone(), 0), 1 << 127));
+        assert_eq!(EscrowSrc::nonce_slot(&maker, 128), ((maker.clone(), 1), 1));
+        assert_eq!(EscrowSrc::nonce_slot(&maker, u64::MAX), ((maker, u64::MAX / 128), 1 << 127));
+    }
+
+    #[test]
+    fn used_nonces_only_mark_their_own_bit() {
+        set_caller("maker.near");
+        let mut contract = EscrowSrc::default();
+        let maker = account("maker.near");
+
+        contract.use_nonce(&maker, 5);
+        contract.use_nonce(&maker, 127);
+        contract.invalidate_nonce(128);
+
+        for nonce in [5, 127, 128] {
+            assert!(contract.is_nonce_used(maker.clone(), nonce));
+        }
+        for nonce in [4, 6, 126, 129, 256] {
+            assert!(!contract.is_nonce_used(maker.clone(), nonce));
+        }
+        assert!(!contract.is_nonce_used(account("other.near"), 5));
+    }
+
+    #[test]
+    #[should_panic(expected = "Nonce already used...")]
+    fn nonce_can_be_used_once() {
+        set_caller("maker.near");
+        let mut contract = EscrowSrc::default();
+        contract.use_nonce(&account("maker.near"), 5);
+        contract.use_nonce(&account("maker.near"), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nonce already used...")]
+    fn invalidated_nonce_can_not_be_used() {
+        set_caller("maker.near");
+        let mut contract = EscrowSrc::default();
+        contract.invalidate_nonce(5);
+        contract.use_nonce(&account("maker.near"), 5);
+    }
+
+    #[test]
+    fn nonces_below_the_minimum_are_used() {
+        set_caller("maker.near");
+        let mut contract = EscrowSrc::default();
+        let maker = account("maker.near");
+
+        contract.invalidate_nonces_below(300);
+        assert!(contract.is_nonce_used(maker.clone(), 0));
+        assert!(contract.is_nonce_used(maker.clone(), 299));
+        assert!(!contract.is_nonce_used(maker.clone(), 300));
+        assert!(!contract.is_nonce_used(account("other.near"), 0));
+
+        contract.use_nonce(&maker, 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nonces can only be invalidated upwards...")]
+    fn minimum_nonce_only_grows() {
+        set_caller("maker.near");
+        let mut contract = EscrowSrc::default();
+        contract.invalidate_nonces_below(300);
+        contract.invalidate_nonces_below(300);
+    }
+
+    #[test]
+    fn increasing_the_epoch_kills_older_orders() {
+        set_caller("maker.near");
+        let mut contract = EscrowSrc::default();
+        assert!(contract.is_current_epoch(&order("maker.near", 0)));
+
+        assert_eq!(contract.increase_epoch(), 1);
+        assert!(!contract.is_current_epoch(&order("maker.near", 0)));
+        assert!(contract.is_current_epoch(&order("maker.near", 1)));
+
+        // epochs are per maker
+        assert_eq!(contract.get_maker_epoch(account("other.near")), 0);
+        assert!(contract.is_current_epoch(&order("other.near", 0)));
+    }
+
+    #[test]
+    fn invalidations_pay_for_their_storage() {
+        set_caller("maker.near");
+        let mut contract = EscrowSrc::default();
+        let initial_usage = env::storage_usage();
+        contract.invalidate_nonce(5);
+        let cost = shared_lib::storage::storage_cost(env::storage_usage() - initial_usage);
+        assert!(cost > ZERO_NEAR);
+        assert_eq!(refunds(), vec![NearToken::from_near(1).saturating_sub(cost)]);
+
+        // another nonce of the same slot adds nothing, the whole deposit comes back
+        set_caller_with_deposit("maker.near", NearToken::from_yoctonear(1));
+        contract.invalidate_nonce(6);
+        assert_eq!(refunds(), vec![NearToken::from_yoctonear(1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient deposit to cover storage...")]
+    fn invalidating_a_nonce_needs_a_deposit() {
+        set_caller_with_deposit("maker.near", ZERO_NEAR);
+        EscrowSrc::default().invalidate_nonce(5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient deposit to cover storage...")]
+    fn invalidating_nonces_below_needs_a_deposit() {
+        set_caller_with_deposit("maker.near", ZERO_NEAR);
+        EscrowSrc::default().invalidate_nonces_below(300);
+    }
+}
@@ -0,0 +1,31 @@
+use shared_lib::secret_registry::RevealedSecret;
+
+use crate::*;
+
+// Secrets revealed by source withdrawals, so resolvers of other fills of a partially filled
+// order can read them here; storage handling lives in shared_lib::secret_registry
+#[near_bindgen]
+impl EscrowSrc {
+    pub fn get_revealed_secret(&self, hashlock: String) -> Option<String> {
+        self.revealed_secrets.get(&hashlock)
+    }
+
+    pub fn get_revealed_secret_by_escrow(&self, immutables_hash: String) -> Option<String> {
+        self.revealed_secrets.get_by_escrow(&immutables_hash)
+    }
+
+    pub fn get_revealed_secrets(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<RevealedSecret> {
+        self.revealed_secrets.list(from_index, limit)
+    }
+
+    pub fn prune_revealed_secrets(&mut self, hashlocks: Vec<String>) -> u32 {
+        self.revealed_secrets.prune_all(&hashlocks)
+    }
+}
+
+impl EscrowSrc {
+    // A fill can be withdrawn until src_cancellation, its secret is kept at least that long
+    pub(crate) fn reveal_secret(&mut self, secret: Option<String>, immutables: &Immutables, timelock: &TimeLock) -> String {
+        self.revealed_secrets.reveal(secret, immutables, timelock.get(Stage::SrcCancellation))
+    }
+}
@@ -0,0 +1,350 @@
+use near_sdk::{json_types::Base64VecU8, CurveType, PublicKey};
+
+use crate::*;
+
+// NEP-413 prefix tag: 2^31 + 413
+const NEP413_TAG: u32 = (1 << 31) + 413;
+const GAS_FOR_RESOLVE_BALANCE_WITHDRAW: Gas = Gas::from_tgas(10);
+
+// Order a maker signs off-chain
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderIntent {
+    pub order: MakerOrder,
+    pub nonce: u64,                     // maker chosen, each nonce can be used once (see nonces.rs)
+    pub deadline: u64                   // timestamp after which the intent can't be placed
+}
+
+// Signed order submitted by a resolver together with its first fill
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SignedMakerOrder {
+    Nep413(Nep413SignedOrder),      // NEAR makers, ed25519 key registered by the maker
+    Eip712(Box<Eip712SignedOrder>)  // EVM makers, maker is the eth-implicit account of the signer
+}
+
+// NEP-413 signed message carrying a JSON encoded OrderIntent
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Nep413SignedOrder {
+    pub message: String,                // JSON encoded OrderIntent
+    pub nonce: Base64VecU8,             // NEP-413 nonce (32 bytes)
+    pub recipient: String,              // NEP-413 recipient, must be this contract
+    pub callback_url: Option<String>,
+    pub public_key: PublicKey,          // ed25519 key registered by the maker
+    pub signature: Base64VecU8          // ed25519 signature (64 bytes)
+}
+
+// OrderIntent signed as EIP-712 typed data (see eip712.rs)
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Eip712SignedOrder {
+    pub intent: OrderIntent,
+    pub signature: String               // hex encoded r || s || v (65 bytes)
+}
+
+#[derive(BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+struct Nep413Payload {
+    message: String,
+    nonce: [u8; 32],
+    recipient: String,
+    callback_url: Option<String>
+}
+
+#[ext_contract(ext_signed_orders)]
+trait _SignedOrderCallbacks {
+    fn on_balance_withdraw_resolved(&mut self, maker: AccountId, token: AccountId, amount: NearToken) -> bool;
+}
+
+#[near_bindgen]
+impl EscrowSrc {
+    // Registers an ed25519 key the caller signs order intents with
+    // The attached deposit pays for the key's storage, extra deposit is refunded
+    #[payable]
+    pub fn add_maker_public_key(&mut self, public_key: PublicKey) {
+        require!(public_key.curve_type() == CurveType::ED25519, "Only ed25519 keys are supported...");
+        let initial_usage = env::storage_usage();
+        self.maker_public_keys.insert((env::predecessor_account_id(), public_key));
+        shared_lib::storage::settle_storage(initial_usage);
+    }
+
+    // Unregisters a key of the caller and refunds its storage deposit
+    pub fn remove_maker_public_key(&mut self, public_key: PublicKey) {
+        let initial_usage = env::storage_usage();
+        require!(self.maker_public_keys.remove(&(env::predecessor_account_id(), public_key)), "Public key is not registered...");
+        shared_lib::storage::settle_storage(initial_usage);
+    }
+
+    pub fn has_maker_public_key(&self, maker: AccountId, public_key: PublicKey) -> bool {
+        self.maker_public_keys.contains(&(maker, public_key))
+    }
+
+    // Tokens the maker deposited for signed orders and that are not locked by any order yet
+    pub fn get_maker_balance(&self, maker: AccountId, token: AccountId) -> NearToken {
+        self.maker_balances.get(&(maker, token)).copied().unwrap_or(ZERO_NEAR)
+    }
+
+    // Takes deposited tokens that are not locked by an order back to the maker
+    pub fn withdraw_maker_balance(&mut self, token: AccountId, amount: NearToken) -> Promise {
+        let maker = env::predecessor_account_id();
+        require!(amount > ZERO_NEAR, "Amount must be greater than zero...");
+        self.debit_maker_balance(&maker, &token, amount);
+
+        self.safe_ft_transfer(token.clone(), maker.clone(), amount)
+            .then(
+                ext_signed_orders::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_BALANCE_WITHDRAW)
+                    .on_balance_withdraw_resolved(maker, token, amount)
+            )
+    }
+
+    // Puts the amount back on the ledger if the withdrawal transfer failed
+    #[private]
+    pub fn on_balance_withdraw_resolved(
+        &mut self,
+        maker: AccountId,
+        token: AccountId,
+        amount: NearToken,
+        #[callback_result] transfer_result: Result<(), PromiseError>
+    ) -> bool {
+        if transfer_result.is_err() {
+            log!("Withdrawal of {} {} failed, restoring balance of {}", amount, token, maker);
+            self.credit_maker_balance(&maker, &token, amount);
+            return false;
+        }
+
+        true
+    }
+}
+
+impl EscrowSrc {
+    pub(crate) fn credit_maker_balance(&mut self, maker: &AccountId, token: &AccountId, amount: NearToken) {
+        let key = (maker.clone(), token.clone());
+        let balance = self.maker_balances.get(&key).copied().unwrap_or(ZERO_NEAR);
+        let balance = balance.checked_add(amount).expect("Overflow when crediting maker balance");
+        self.maker_balances.insert(key, balance);
+    }
+
+    pub(crate) fn debit_maker_balance(&mut self, maker: &AccountId, token: &AccountId, amount: NearToken) {
+        let key = (maker.clone(), token.clone());
+        let balance = self.maker_balances.get(&key).copied().unwrap_or(ZERO_NEAR);
+        let balance = balance.checked_sub(amount).expect("Insufficient maker balance...");
+
+        if balance == ZERO_NEAR {
+            self.maker_balances.remove(&key);
+        } else {
+            self.maker_balances.insert(key, balance);
+        }
+    }
+
+    // Verifies a maker's signed intent and turns it into a MakerOrder backed by the
+    // maker's deposited balance
+    pub(crate) fn place_signed_order(&mut self, signed_order: SignedMakerOrder) {
+        let intent = match signed_order {
+            SignedMakerOrder::Nep413(signed_order) => self.verify_nep413_order(signed_order),
+            SignedMakerOrder::Eip712(signed_order) => Self::verify_eip712_order(*signed_order)
+        };
+        let maker_order = intent.order;
+
+        require!(env::block_timestamp() < intent.deadline, "Intent deadline has passed...");
+        self.use_nonce(&maker_order.maker, intent.nonce);
+        self.validate_new_maker_order(&maker_order);
+
+        // lock the order amount out of the maker's deposit, only fungible tokens can be deposited
+        let Asset::Ft(token) = &maker_order.token else {
+            env::panic_str("Signed orders only support fungible tokens...");
+        };
+        self.debit_maker_balance(&maker_order.maker, token, maker_order.total_amount);
+        self.store_maker_order(maker_order);
+    }
+
+    fn verify_nep413_order(&self, signed_order: Nep413SignedOrder) -> OrderIntent {
+        require!(signed_order.recipient == env::current_account_id().as_str(), "Intent is signed for another contract...");
+
+        let intent: OrderIntent = near_sdk::serde_json::from_str(&signed_order.message)
+            .expect("Invalid order intent...");
+
+        require!(
+            self.maker_public_keys.contains(&(intent.order.maker.clone(), signed_order.public_key.clone())),
+            "Public key is not registered by the maker..."
+        );
+        require!(Self::verify_nep413_signature(&signed_order), "Invalid intent signature...");
+
+        intent
+    }
+
+    fn verify_eip712_order(signed_order: Eip712SignedOrder) -> OrderIntent {
+        let signature = hex::decode(signed_order.signature.trim_start_matches("0x"))
+            .expect("Invalid signature hex...");
+
+        let digest = eip712::order_digest(&signed_order.intent);
+        let signer = eip712::recover_address(&digest, &signature)
+            .expect("Invalid intent signature...");
+        require!(signed_order.intent.order.maker.as_str() == eip712::evm_account(&signer), "Intent is not signed by the maker...");
+
+        signed_order.intent
+    }
+
+    fn verify_nep413_signature(signed_order: &Nep413SignedOrder) -> bool {
+        let nonce: [u8; 32] = signed_order.nonce.0.as_slice().try_into()
+            .expect("NEP-413 nonce must be 32 bytes...");
+        let signature: [u8; 64] = signed_order.signature.0.as_slice().try_into()
+            .expect("Signature must be 64 bytes...");
+        let public_key: [u8; 32] = signed_order.public_key.as_bytes()[1..].try_into()
+            .expect("Invalid ed25519 public key...");
+
+        let payload = Nep413Payload {
+            message: signed_order.message.clone(),
+            nonce,
+            recipient: signed_order.recipient.clone(),
+            callback_url: signed_order.callback_url.clone()
+        };
+
+        let mut data = NEP413_TAG.to_le_bytes().to_vec();
+        data.extend(near_sdk::borsh::to_vec(&payload).expect("Failed to serialize NEP-413 payload"));
+
+        env::ed25519_verify(&signature, env::sha256(&data), &public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+    use near_sdk::{mock::MockAction, test_utils::{get_created_receipts, VMContextBuilder}, testing_env};
+
+    use super::*;
+
+    fn set_maker(deposit: NearToken) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("escrow.near".parse().unwrap())
+            .predecessor_account_id("maker.near".parse().unwrap())
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    fn refunds() -> Vec<NearToken> {
+        get_created_receipts().into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                MockAction::Transfer { deposit, .. } => Some(deposit),
+                _ => None
+            })
+            .collect()
+    }
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn public_key(key: &SigningKey) -> PublicKey {
+        PublicKey::from_parts(CurveType::ED25519, key.verifying_key().to_bytes().to_vec()).unwrap()
+    }
+
+    // signs the NEP-413 payload the way wallets do: sha256(tag || borsh(payload))
+    fn signed_order(key: &SigningKey, message: &str, recipient: &str) -> Nep413SignedOrder {
+        let payload = Nep413Payload {
+            message: message.to_string(),
+            nonce: [1; 32],
+            recipient: recipient.to_string(),
+            callback_url: None
+        };
+        let mut data = NEP413_TAG.to_le_bytes().to_vec();
+        data.extend(near_sdk::borsh::to_vec(&payload).unwrap());
+        let signature = key.sign(&env::sha256(&data));
+
+        Nep413SignedOrder {
+            message: payload.message,
+            nonce: Base64VecU8(payload.nonce.to_vec()),
+            recipient: payload.recipient,
+            callback_url: None,
+            public_key: public_key(key),
+            signature: Base64VecU8(signature.to_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn nep413_tag_is_prefixed() {
+        assert_eq!(NEP413_TAG, 2_147_484_061);
+    }
+
+    #[test]
+    fn valid_nep413_signature_passes() {
+        let order = signed_order(&signing_key(), "intent", "escrow.near");
+        assert!(EscrowSrc::verify_nep413_signature(&order));
+    }
+
+    #[test]
+    fn every_payload_field_is_signed() {
+        let key = signing_key();
+
+        let mut order = signed_order(&key, "intent", "escrow.near");
+        order.message = "other intent".to_string();
+        assert!(!EscrowSrc::verify_nep413_signature(&order));
+
+        let mut order = signed_order(&key, "intent", "escrow.near");
+        order.nonce = Base64VecU8(vec![2; 32]);
+        assert!(!EscrowSrc::verify_nep413_signature(&order));
+
+        let mut order = signed_order(&key, "intent", "escrow.near");
+        order.recipient = "other.near".to_string();
+        assert!(!EscrowSrc::verify_nep413_signature(&order));
+
+        let mut order = signed_order(&key, "intent", "escrow.near");
+        order.callback_url = Some("https://example.com".to_string());
+        assert!(!EscrowSrc::verify_nep413_signature(&order));
+    }
+
+    #[test]
+    fn signature_of_another_key_fails() {
+        let mut order = signed_order(&signing_key(), "intent", "escrow.near");
+        order.public_key = public_key(&SigningKey::from_bytes(&[8; 32]));
+        assert!(!EscrowSrc::verify_nep413_signature(&order));
+    }
+
+    #[test]
+    #[should_panic(expected = "NEP-413 nonce must be 32 bytes...")]
+    fn short_nonce_is_rejected() {
+        let mut order = signed_order(&signing_key(), "intent", "escrow.near");
+        order.nonce = Base64VecU8(vec![1; 31]);
+        EscrowSrc::verify_nep413_signature(&order);
+    }
+
+    #[test]
+    #[should_panic(expected = "Intent is signed for another contract...")]
+    fn intent_for_another_contract_is_rejected() {
+        testing_env!(VMContextBuilder::new().current_account_id("escrow.near".parse().unwrap()).build());
+        let order = signed_order(&signing_key(), "intent", "other.near");
+        EscrowSrc::default().verify_nep413_order(order);
+    }
+
+    #[test]
+    fn key_storage_is_paid_on_add_and_refunded_on_remove() {
+        set_maker(NearToken::from_near(1));
+        let mut contract = EscrowSrc::default();
+        let initial_usage = env::storage_usage();
+        contract.add_maker_public_key(public_key(&signing_key()));
+        let cost = shared_lib::storage::storage_cost(env::storage_usage() - initial_usage);
+        assert!(cost > ZERO_NEAR);
+        assert_eq!(refunds(), vec![NearToken::from_near(1).saturating_sub(cost)]);
+
+        set_maker(ZERO_NEAR);
+        contract.remove_maker_public_key(public_key(&signing_key()));
+        assert_eq!(refunds(), vec![cost]);
+        assert!(!contract.has_maker_public_key("maker.near".parse().unwrap(), public_key(&signing_key())));
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient deposit to cover storage...")]
+    fn adding_a_key_needs_a_deposit() {
+        set_maker(ZERO_NEAR);
+        EscrowSrc::default().add_maker_public_key(public_key(&signing_key()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Public key is not registered...")]
+    fn only_registered_keys_are_refunded() {
+        set_maker(ZERO_NEAR);
+        EscrowSrc::default().remove_maker_public_key(public_key(&signing_key()));
+    }
+}
@@ -0,0 +1,299 @@
+use shared_lib::assets::Asset;
+
+use crate::*;
+
+pub const SLASH_BPS_DENOMINATOR: u16 = 10_000;
+pub const DEFAULT_SLASH_BPS: u16 = 5_000;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct ResolverStake {
+    pub bonded: NearToken,          // NEAR bonded by the resolver
+    pub locked: NearToken           // part of bonded backing active fills
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ResolverStakeView {
+    pub bonded: NearToken,
+    pub locked: NearToken,
+    pub available: NearToken
+}
+
+// Stake required for filling a making asset: `stake` NEAR per `per_amount` of the asset,
+// counted in its own smallest units (e.g. 1 NEAR per 1_000_000 of a 6 decimals token, per 1 NFT)
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct StakeRate {
+    pub stake: NearToken,
+    pub per_amount: NearToken
+}
+
+impl ResolverStake {
+    fn available(&self) -> NearToken {
+        self.bonded.saturating_sub(self.locked)
+    }
+}
+
+#[near_bindgen]
+impl EscrowSrc {
+    // Bonds the attached NEAR as the caller's resolver stake
+    #[payable]
+    pub fn bond_stake(&mut self) {
+        let amount = env::attached_deposit();
+        require!(amount > ZERO_NEAR, "Nothing to bond...");
+
+        let stake = self.resolver_stakes.entry(env::predecessor_account_id()).or_default();
+        stake.bonded = stake.bonded.checked_add(amount).expect("Overflow when bonding stake");
+    }
+
+    // Returns stake that doesn't back any active fill
+    pub fn unbond_stake(&mut self, amount: NearToken) -> Promise {
+        let resolver = env::predecessor_account_id();
+        let stake = self.resolver_stakes.get_mut(&resolver).expect("No stake bonded...");
+        require!(amount > ZERO_NEAR && amount <= stake.available(), "Amount exceeds available stake...");
+
+        stake.bonded = stake.bonded.saturating_sub(amount);
+        if stake.bonded == ZERO_NEAR {
+            self.resolver_stakes.remove(&resolver);
+        }
+
+        Promise::new(resolver).transfer(amount)
+    }
+
+    // Owner sets the stake required per `per_amount` of an asset, a zero stake disables the requirement
+    pub fn set_stake_rate(&mut self, token: Asset, stake: NearToken, per_amount: NearToken) {
+        self.resolver_registry.assert_owner();
+        if stake == ZERO_NEAR {
+            self.stake_rates.remove(&token);
+        } else {
+            require!(per_amount > ZERO_NEAR, "Stake rate amount must be greater than zero...");
+            self.stake_rates.insert(token, StakeRate { stake, per_amount });
+        }
+    }
+
+    // Owner sets the share of a fill's locked stake paid to the maker on public cancellation
+    pub fn set_slash_bps(&mut self, slash_bps: u16) {
+        self.resolver_registry.assert_owner();
+        require!(slash_bps <= SLASH_BPS_DENOMINATOR, "Slash share can't exceed 100%...");
+        self.slash_bps = slash_bps;
+    }
+
+    pub fn get_resolver_stake(&self, resolver: AccountId) -> ResolverStakeView {
+        let stake = self.resolver_stakes.get(&resolver).cloned().unwrap_or_default();
+        ResolverStakeView {
+            bonded: stake.bonded,
+            locked: stake.locked,
+            available: stake.available()
+        }
+    }
+
+    pub fn get_stake_rate(&self, token: Asset) -> Option<StakeRate> {
+        self.stake_rates.get(&token).cloned()
+    }
+
+    pub fn get_slash_bps(&self) -> u16 {
+        self.slash_bps
+    }
+}
+
+impl EscrowSrc {
+    // Stake a fill of `making_amount` keeps locked until it is settled
+    pub(crate) fn required_stake(&self, making_token: &Asset, making_amount: &NearToken) -> NearToken {
+        let Some(rate) = self.stake_rates.get(making_token) else {
+            return ZERO_NEAR;
+        };
+        let required = shared_lib::utils::mul_div(making_amount.as_yoctonear(), rate.stake.as_yoctonear(), rate.per_amount.as_yoctonear(), true)
+            .expect("Overflow when calculating required stake");
+        NearToken::from_yoctonear(required)
+    }
+
+    pub(crate) fn lock_stake(&mut self, resolver: &AccountId, amount: NearToken) {
+        if amount == ZERO_NEAR {
+            return;
+        }
+
+        let stake = self.resolver_stakes.get_mut(resolver).expect("Not enough stake bonded...");
+        require!(amount <= stake.available(), "Not enough stake bonded...");
+        stake.locked = stake.locked.saturating_add(amount);
+    }
+
+    // Releases the stake of a settled fill, slashing a share of it to the maker if the
+    // fill had to be publicly cancelled. Returns the slashed amount.
+    pub(crate) fn release_stake(&mut self, resolver: &AccountId, amount: NearToken, slash: bool) -> NearToken {
+        if amount == ZERO_NEAR {
+            return ZERO_NEAR;
+        }
+
+        let slashed = if slash {
+            NearToken::from_yoctonear(amount.as_yoctonear() * self.slash_bps as u128 / SLASH_BPS_DENOMINATOR as u128)
+        } else {
+            ZERO_NEAR
+        };
+
+        if let Some(stake) = self.resolver_stakes.get_mut(resolver) {
+            stake.locked = stake.locked.saturating_sub(amount);
+            stake.bonded = stake.bonded.saturating_sub(slashed);
+        }
+
+        slashed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    use super::*;
+
+    const OWNER: &str = "escrow.near";
+    const RESOLVER: &str = "resolver.near";
+
+    fn near(yocto: u128) -> NearToken {
+        NearToken::from_yoctonear(yocto)
+    }
+
+    fn token() -> Asset {
+        Asset::Ft("token.near".parse().unwrap())
+    }
+
+    fn set_caller(caller: &str, deposit: NearToken) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(OWNER.parse().unwrap())
+            .predecessor_account_id(caller.parse().unwrap())
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    // contract with RESOLVER holding `bonded` of stake
+    fn contract(bonded: u128) -> EscrowSrc {
+        set_caller(RESOLVER, near(bonded));
+        let mut contract = EscrowSrc::default();
+        contract.bond_stake();
+        contract
+    }
+
+    #[test]
+    fn required_stake_scales_with_the_rate_and_rounds_up() {
+        set_caller(OWNER, ZERO_NEAR);
+        let mut contract = EscrowSrc::default();
+        assert_eq!(contract.required_stake(&token(), &near(1_000)), ZERO_NEAR);
+
+        contract.set_stake_rate(token(), NearToken::from_near(2), NearToken::from_near(1));
+        assert_eq!(contract.required_stake(&token(), &NearToken::from_millinear(1_500)), NearToken::from_near(3));
+        assert_eq!(contract.required_stake(&token(), &near(1)), near(2));
+        assert_eq!(contract.required_stake(&Asset::Near, &near(1_000)), ZERO_NEAR);
+
+        // 1 / 3 of a yocto rounds up
+        contract.set_stake_rate(token(), near(1), near(3));
+        assert_eq!(contract.required_stake(&token(), &near(1)), near(1));
+
+        contract.set_stake_rate(token(), ZERO_NEAR, ZERO_NEAR);
+        assert_eq!(contract.get_stake_rate(token()), None);
+    }
+
+    #[test]
+    fn rates_scale_low_decimal_assets_and_nfts() {
+        set_caller(OWNER, ZERO_NEAR);
+        let mut contract = EscrowSrc::default();
+
+        // 10 NEAR per whole unit of a 6 decimals token
+        contract.set_stake_rate(token(), NearToken::from_near(10), near(1_000_000));
+        assert_eq!(contract.required_stake(&token(), &near(2_500_000)), NearToken::from_near(25));
+
+        // 100 NEAR per NFT
+        let nft = Asset::Nft { contract: "nft.near".parse().unwrap(), token_id: "1".to_string() };
+        contract.set_stake_rate(nft.clone(), NearToken::from_near(100), near(1));
+        assert_eq!(contract.required_stake(&nft, &NFT_AMOUNT), NearToken::from_near(100));
+        assert_eq!(contract.get_stake_rate(nft), Some(StakeRate { stake: NearToken::from_near(100), per_amount: near(1) }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Stake rate amount must be greater than zero...")]
+    fn stake_rates_need_an_amount() {
+        set_caller(OWNER, ZERO_NEAR);
+        EscrowSrc::default().set_stake_rate(token(), near(1), ZERO_NEAR);
+    }
+
+    #[test]
+    fn locked_stake_is_not_available() {
+        let mut contract = contract(1_000);
+        let resolver = RESOLVER.parse().unwrap();
+
+        contract.lock_stake(&resolver, near(600));
+        contract.lock_stake(&resolver, near(400));
+        let stake = contract.get_resolver_stake(resolver);
+        assert_eq!((stake.bonded, stake.locked, stake.available), (near(1_000), near(1_000), ZERO_NEAR));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough stake bonded...")]
+    fn lock_can_not_exceed_available_stake() {
+        let mut contract = contract(1_000);
+        let resolver = RESOLVER.parse().unwrap();
+        contract.lock_stake(&resolver, near(600));
+        contract.lock_stake(&resolver, near(401));
+    }
+
+    #[test]
+    fn zero_stake_needs_no_bond() {
+        set_caller(RESOLVER, ZERO_NEAR);
+        let mut contract = EscrowSrc::default();
+        let resolver = RESOLVER.parse().unwrap();
+        contract.lock_stake(&resolver, ZERO_NEAR);
+        assert_eq!(contract.release_stake(&resolver, ZERO_NEAR, true), ZERO_NEAR);
+    }
+
+    #[test]
+    fn release_unlocks_without_slashing() {
+        let mut contract = contract(1_000);
+        let resolver: AccountId = RESOLVER.parse().unwrap();
+        contract.lock_stake(&resolver, near(600));
+
+        assert_eq!(contract.release_stake(&resolver, near(600), false), ZERO_NEAR);
+        let stake = contract.get_resolver_stake(resolver);
+        assert_eq!((stake.bonded, stake.locked, stake.available), (near(1_000), ZERO_NEAR, near(1_000)));
+    }
+
+    #[test]
+    fn slash_takes_a_share_of_the_locked_stake() {
+        let mut contract = contract(1_000);
+        let resolver: AccountId = RESOLVER.parse().unwrap();
+        contract.lock_stake(&resolver, near(601));
+
+        // 601 * 50% rounds down to 300
+        assert_eq!(contract.release_stake(&resolver, near(601), true), near(300));
+        let stake = contract.get_resolver_stake(resolver.clone());
+        assert_eq!((stake.bonded, stake.locked, stake.available), (near(700), ZERO_NEAR, near(700)));
+
+        set_caller(OWNER, ZERO_NEAR);
+        contract.set_slash_bps(SLASH_BPS_DENOMINATOR);
+        contract.lock_stake(&resolver, near(700));
+        assert_eq!(contract.release_stake(&resolver, near(700), true), near(700));
+        assert_eq!(contract.get_resolver_stake(resolver).bonded, ZERO_NEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "Slash share can't exceed 100%...")]
+    fn slash_share_is_capped() {
+        set_caller(OWNER, ZERO_NEAR);
+        EscrowSrc::default().set_slash_bps(SLASH_BPS_DENOMINATOR + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Amount exceeds available stake...")]
+    fn locked_stake_can_not_be_unbonded() {
+        let mut contract = contract(1_000);
+        contract.lock_stake(&RESOLVER.parse().unwrap(), near(600));
+        contract.unbond_stake(near(401)).detach();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can manage resolvers...")]
+    fn only_owner_sets_stake_rates() {
+        set_caller(RESOLVER, ZERO_NEAR);
+        EscrowSrc::default().set_stake_rate(token(), near(1), near(1));
+    }
+}
@@ -0,0 +1,74 @@
+use shared_lib::{escrow_status::EscrowStage, utils::page};
+
+use crate::*;
+
+// A resolver fill together with where it is on its timeline
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EscrowView {
+    pub immutables_hash: String,
+    pub fill: ResolverOrderFill,
+    pub stage: EscrowStage
+}
+
+#[near_bindgen]
+impl EscrowSrc {
+    pub fn get_maker_order(&self, order_root_hash: String) -> Option<MakerOrder> {
+        self.makers_orders.get(&order_root_hash).cloned()
+    }
+
+    // Orders that can still be filled, optionally only of one token and/or maker
+    // A maker or token filter pages through that maker's (or token's) index only
+    pub fn get_open_maker_orders(
+        &self,
+        token: Option<Asset>,
+        maker: Option<AccountId>,
+        from_index: Option<u32>,
+        limit: Option<u32>
+    ) -> Vec<MakerOrder> {
+        let orders: Box<dyn Iterator<Item = &MakerOrder>> = match (&maker, &token) {
+            (Some(maker), _) => Box::new(self.indexed_orders(self.orders_by_maker.iter(maker))),
+            (None, Some(token)) => Box::new(self.indexed_orders(self.orders_by_token.iter(token))),
+            (None, None) => Box::new(self.makers_orders.values())
+        };
+
+        let open_orders = orders
+            .filter(|order| self.is_open(order))
+            .filter(|order| token.as_ref().is_none_or(|token| order.token == *token));
+        page(open_orders, from_index, limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_escrow(&self, immutables_hash: String) -> Option<EscrowView> {
+        self.resolver_orders.get(&immutables_hash)
+            .map(|fill| Self::escrow_view(&immutables_hash, fill))
+    }
+
+    pub fn get_escrows_by_taker(&self, taker: AccountId, from_index: Option<u32>, limit: Option<u32>) -> Vec<EscrowView> {
+        page(self.fills_by_taker.iter(&taker), from_index, limit)
+            .filter_map(|immutables_hash| self.get_escrow(immutables_hash.clone()))
+            .collect()
+    }
+}
+
+impl EscrowSrc {
+    fn indexed_orders<'a>(&'a self, root_hashes: impl Iterator<Item = &'a String> + 'a) -> impl Iterator<Item = &'a MakerOrder> + 'a {
+        root_hashes.filter_map(|root_hash| self.makers_orders.get(root_hash))
+    }
+
+    fn is_open(&self, maker_order: &MakerOrder) -> bool {
+        maker_order.cancelled_amount == ZERO_NEAR
+            && maker_order.filled_amount < maker_order.total_amount
+            && env::block_timestamp() < maker_order.expiration
+            && self.is_current_epoch(maker_order)
+    }
+
+    fn escrow_view(immutables_hash: &str, fill: &ResolverOrderFill) -> EscrowView {
+        EscrowView {
+            immutables_hash: immutables_hash.to_string(),
+            stage: EscrowStage::src(&fill.immutables.timelock, &fill.status),
+            fill: fill.clone()
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use shared_lib::{assets::Asset, near_wrapper::{NearPayouts, NearWrapper}};
+
+use crate::*;
+
+// convert_near orders: resolvers of a native NEAR order are paid in wNEAR, makers of a wNEAR
+// order are refunded in NEAR. The conversion itself lives in shared_lib::near_wrapper.
+#[near_bindgen]
+impl EscrowSrc {
+    // Owner sets the wNEAR contract NEAR orders pay resolvers in, fills pin it when placed.
+    // This escrow has to be registered on it (storage_deposit) for wrapping to succeed.
+    pub fn set_wrap_account(&mut self, wrap_account: Option<AccountId>) {
+        self.resolver_registry.assert_owner();
+        self.near_wrapper.set_wrap_account(wrap_account);
+    }
+
+    pub fn get_wrap_account(&self) -> Option<AccountId> {
+        self.near_wrapper.wrap_account().cloned()
+    }
+
+    // Resolver payout of a native NEAR order, see NearWrapper::resolve_wrap
+    #[private]
+    pub fn on_near_wrapped(
+        &mut self,
+        settlement_id: String,
+        wrap_account: AccountId,
+        receiver_id: AccountId,
+        amount: NearToken,
+        #[callback_result] wrap_result: Result<(), PromiseError>
+    ) -> Promise {
+        NearWrapper::resolve_wrap(self, settlement_id, wrap_account, receiver_id, amount, wrap_result)
+    }
+
+    // Maker refund of a wNEAR order in native NEAR, see NearWrapper::resolve_unwrap
+    #[private]
+    pub fn on_near_unwrapped(
+        &mut self,
+        settlement_id: String,
+        wrap_account: AccountId,
+        receiver_id: AccountId,
+        amount: NearToken,
+        #[callback_result] unwrap_result: Result<(), PromiseError>
+    ) -> Promise {
+        NearWrapper::resolve_unwrap(self, settlement_id, wrap_account, receiver_id, amount, unwrap_result)
+    }
+}
+
+impl EscrowSrc {
+    pub(crate) fn is_wrapped_near(&self, asset: &Asset) -> bool {
+        self.near_wrapper.is_wrapped_near(asset)
+    }
+}
+
+impl NearPayouts for EscrowSrc {
+    fn pay_out(&mut self, asset: &Asset, receiver_id: AccountId, amount: NearToken) -> Promise {
+        self.transfer_asset(asset, receiver_id, amount)
+    }
+
+    // A fill now holds the converted asset, a re-activated fill pays it out from there.
+    // Maker refunds are pooled with the order's other funds, so their conversion is kept
+    // aside and reverted by on_cancel_order_resolved if the refund fails.
+    fn record_conversion(&mut self, settlement_id: &str, asset: Asset) {
+        if let Some(fill) = self.resolver_orders.get_mut(settlement_id) {
+            fill.held_asset = asset;
+        } else if self.makers_orders.contains_key(settlement_id) {
+            self.converted_refunds.insert(settlement_id.to_string(), asset);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, serde::{Deserialize, Serialize}, AccountId, NearSchema};
+
+// Canonical name of native NEAR, ':' keeps it from ever being a token account id
+pub const NATIVE_NEAR: &str = "native:near";
+
+// Prefix of NEP-245 assets: "nep245:<contract>:<token_id>"
+pub const MT_ASSET_PREFIX: &str = "nep245:";
+
+// Prefix of NEP-171 assets: "nep171:<contract>:<token_id>"
+pub const NFT_ASSET_PREFIX: &str = "nep171:";
+
+// Prefix of tokens living on another chain: "foreign:<token>"
+pub const FOREIGN_ASSET_PREFIX: &str = "foreign:";
+
+// What a maker order or an escrow's making_token / taking_token refers to
+// Immutables::hash() covers the borsh encoding, so token ids of multi tokens and NFTs are bound too
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum Asset {
+    Near,                   // native NEAR, paid out with Promise::transfer
+    Ft(AccountId),          // NEP-141 token contract
+    Mt {                    // NEP-245 token of a multi token contract
+        contract: AccountId,
+        token_id: String
+    },
+    Nft {                   // NEP-171 non-fungible token, always an amount of 1
+        contract: AccountId,
+        token_id: String
+    },
+    Foreign(String)         // token on the other chain of the swap, never paid out here
+}
+
+impl Asset {
+    // Assets this chain can hold in escrow and pay out
+    pub fn is_local(&self) -> bool {
+        !matches!(self, Self::Foreign(_))
+    }
+}
+
+// Canonical string of an asset, used where a signed string is needed (EIP-712 orders).
+// Only Ft has no prefix, and account ids can't contain ':', so the strings never collide.
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Near => write!(f, "{}", NATIVE_NEAR),
+            Self::Ft(contract) => write!(f, "{}", contract),
+            Self::Mt { contract, token_id } => write!(f, "{}{}:{}", MT_ASSET_PREFIX, contract, token_id),
+            Self::Nft { contract, token_id } => write!(f, "{}{}:{}", NFT_ASSET_PREFIX, contract, token_id),
+            Self::Foreign(token) => write!(f, "{}{}", FOREIGN_ASSET_PREFIX, token)
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, serde::{Deserialize, Serialize}, NearSchema};
+
+use crate::{immutables::{Stage, TimeLock}, utils::_only_after};
+
+// Lifecycle of a single escrow (one fill of a maker order)
+// An escrow leaves Active exactly once; the entry is removed after its payout succeeds
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum EscrowStatus {
+    Active,         // funds locked, waiting for secret or timeout
+    Withdrawn,      // secret revealed, payout to the receiver in flight
+    Cancelled       // timed out, refund in flight
+}
+
+// Point of an escrow's timeline, derived from its timelock and status
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EscrowStage {
+    FinalityLock,           // waiting for chain finality, nothing can be settled yet
+    PrivateWithdrawal,      // taker can withdraw with the secret
+    PublicWithdrawal,       // anyone can withdraw with the secret
+    PrivateCancellation,    // taker can cancel
+    PublicCancellation,     // anyone can cancel
+    Settling                // withdrawal or cancellation payout in flight
+}
+
+impl EscrowStage {
+    pub fn src(timelock: &TimeLock, status: &EscrowStatus) -> Self {
+        Self::at(timelock, status, [Stage::SrcWithdrawal, Stage::SrcPublicWithdrawal, Stage::SrcCancellation, Stage::SrcPublicCancellation])
+    }
+
+    pub fn dst(timelock: &TimeLock, status: &EscrowStatus) -> Self {
+        Self::at(timelock, status, [Stage::DstWithdrawal, Stage::DstPublicWithdrawal, Stage::DstCancellation, Stage::DstPublicCancellation])
+    }
+
+    // `stages` are the chain's withdrawal, public withdrawal, cancellation and public cancellation
+    fn at(timelock: &TimeLock, status: &EscrowStatus, stages: [Stage; 4]) -> Self {
+        if *status != EscrowStatus::Active {
+            return Self::Settling;
+        }
+
+        let [withdrawal, public_withdrawal, cancellation, public_cancellation] = stages;
+        if _only_after(timelock, public_cancellation) {
+            Self::PublicCancellation
+        } else if _only_after(timelock, cancellation) {
+            Self::PrivateCancellation
+        } else if _only_after(timelock, public_withdrawal) {
+            Self::PublicWithdrawal
+        } else if _only_after(timelock, withdrawal) {
+            Self::PrivateWithdrawal
+        } else {
+            Self::FinalityLock
+        }
+    }
+}
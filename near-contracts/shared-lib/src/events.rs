@@ -0,0 +1,155 @@
+use near_sdk::{env, serde::{Deserialize, Serialize}, serde_json::{self, Value}, AccountId, NearSchema, NearToken};
+
+use crate::{assets::Asset, immutables::Immutables};
+
+// NEP-297 standard the escrow contracts emit their events under
+pub const EVENT_STANDARD: &str = "htlc-escrow";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+// Every state transition of maker orders and escrows
+// Emitted as: EVENT_JSON:{"standard","version","event","data":[{...}]}, data being a list
+// of event objects as in NEP-141/NEP-171. Transitions that wait for a payout (withdrawals,
+// cancellations) are only emitted once the payout succeeded.
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde", tag = "event", content = "data", rename_all = "snake_case")]
+pub enum EscrowEvent {
+    OrderCreated(OrderEvent),               // maker order placed on the source escrow
+    OrderFilled(OrderFilledEvent),          // part of a maker order taken by a resolver
+    OrderCancelled(OrderEvent),             // maker cancelled the unfilled remainder
+    OrderExpired(OrderEvent),               // maker reclaimed the unfilled remainder after expiration
+    SrcEscrowCreated(EscrowCreatedEvent),
+    DstEscrowCreated(EscrowCreatedEvent),
+    SafetyDepositAdded(SafetyDepositEvent),
+    Withdrawn(WithdrawnEvent),              // secret revealed, funds sent to the receiver
+    Cancelled(CancelledEvent),              // private cancellation by the taker
+    PublicCancelled(CancelledEvent)         // public cancellation by anyone
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderEvent {
+    pub order_root_hash: String,
+    pub maker: AccountId,
+    pub token: Asset,
+    pub amount: NearToken
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderFilledEvent {
+    pub order_root_hash: String,
+    pub immutables_hash: String,
+    pub taker: AccountId,
+    pub making_amount: NearToken,
+    pub filled_amount: NearToken            // order's filled amount after this fill
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EscrowCreatedEvent {
+    pub immutables_hash: String,
+    pub order_root_hash: String,
+    pub hashlock: String,
+    pub maker: String,
+    pub taker: String,
+    pub receiver: String,
+    pub making_token: Asset,
+    pub making_amount: NearToken,
+    pub taking_token: Asset,
+    pub taking_amount: NearToken,
+    pub safety_deposit: NearToken,
+    pub deployed_at: u32
+}
+
+impl EscrowCreatedEvent {
+    pub fn new(immutables_hash: String, immutables: &Immutables, safety_deposit: NearToken) -> Self {
+        Self {
+            immutables_hash,
+            order_root_hash: immutables.order_root_hash.clone(),
+            hashlock: immutables.hashlock.clone(),
+            maker: immutables.maker.clone(),
+            taker: immutables.taker.clone(),
+            receiver: immutables.receiver.clone(),
+            making_token: immutables.making_token.clone(),
+            making_amount: immutables.making_amount,
+            taking_token: immutables.taking_token.clone(),
+            taking_amount: immutables.taking_amount,
+            safety_deposit,
+            deployed_at: immutables.timelock.deployed_at
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SafetyDepositEvent {
+    pub immutables_hash: String,
+    pub account_id: AccountId,
+    pub amount: NearToken
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawnEvent {
+    pub immutables_hash: String,
+    pub hashlock: String,
+    pub secret: String,
+    pub receiver: AccountId,
+    pub token: Asset,
+    pub amount: NearToken,
+    pub caller: AccountId
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CancelledEvent {
+    pub immutables_hash: String,
+    pub receiver: AccountId,
+    pub token: Asset,
+    pub amount: NearToken,
+    pub caller: AccountId
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog {
+    standard: &'static str,
+    version: &'static str,
+    event: Value,
+    data: Vec<Value>
+}
+
+impl EscrowEvent {
+    pub fn emit(&self) {
+        let mut event = serde_json::to_value(self).expect("Failed to serialize event");
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event: event["event"].take(),
+            data: vec![event["data"].take()]
+        };
+        let json = serde_json::to_string(&log).expect("Failed to serialize event");
+        env::log_str(&format!("EVENT_JSON:{}", json));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::get_logs;
+
+    use super::*;
+
+    #[test]
+    fn emits_data_as_a_list_of_objects() {
+        EscrowEvent::OrderCreated(OrderEvent {
+            order_root_hash: "root".to_string(),
+            maker: "maker.near".parse().unwrap(),
+            token: Asset::Ft("token.near".parse().unwrap()),
+            amount: NearToken::from_yoctonear(1000)
+        }).emit();
+
+        assert_eq!(get_logs(), vec![
+            r#"EVENT_JSON:{"standard":"htlc-escrow","version":"1.0.0","event":"order_created","data":[{"order_root_hash":"root","maker":"maker.near","token":{"Ft":"token.near"},"amount":"1000"}]}"#
+        ]);
+    }
+}
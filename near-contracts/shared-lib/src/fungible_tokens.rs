@@ -1,7 +1,23 @@
 use near_sdk::serde::{Serialize, Deserialize};
-use near_sdk::{ext_contract, NearToken};
+use near_sdk::{ext_contract, Gas, NearToken};
 use near_sdk::{AccountId, Promise};
 
+// Gas of the calls a safe FT transfer is made of (storage_balance_of, then ft_transfer,
+// or storage_deposit followed by ft_transfer for an unregistered receiver)
+pub const GAS_FOR_STORAGE_BALANCE_OF: Gas = Gas::from_tgas(10);
+pub const GAS_FOR_STORAGE_DEPOSIT: Gas = Gas::from_tgas(20);
+pub const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(15);
+
+// Gas a callback spends on its own, on top of the calls it schedules
+pub const GAS_FOR_CALLBACK_EXECUTION: Gas = Gas::from_tgas(5);
+
+// The callbacks of a safe FT transfer carry the gas of every call they schedule:
+// on_storage_deposit schedules ft_transfer, on_check_storage schedules storage_deposit and on_storage_deposit
+pub const GAS_FOR_ON_STORAGE_DEPOSIT: Gas = Gas::from_tgas(25);
+pub const GAS_FOR_ON_CHECK_STORAGE: Gas = Gas::from_tgas(50);
+
+// Whole safe FT transfer, for callbacks that end in one
+pub const GAS_FOR_SAFE_FT_TRANSFER: Gas = Gas::from_tgas(60);
 
 #[ext_contract(ext_ft)]
 pub trait FungibleToken {
@@ -56,3 +72,28 @@ pub struct StorageBalance {
     pub total: NearToken,
     pub available: NearToken,
 }
+
+// wNEAR (e.g. wrap.near) on top of its NEP-141 interface
+#[ext_contract(ext_wrap)]
+pub trait WrappedNear {
+    // Wraps the attached NEAR into wNEAR for the caller
+    fn near_deposit(&mut self);
+
+    // Unwraps wNEAR of the caller back to NEAR, requires 1 yoctoNEAR
+    fn near_withdraw(&mut self, amount: NearToken) -> Promise;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callbacks_cover_the_calls_they_schedule() {
+        let exec = GAS_FOR_CALLBACK_EXECUTION.as_tgas();
+        assert!(GAS_FOR_ON_STORAGE_DEPOSIT.as_tgas() >= exec + GAS_FOR_FT_TRANSFER.as_tgas());
+        // the unregistered receiver path is the expensive one
+        assert!(GAS_FOR_ON_CHECK_STORAGE.as_tgas() >= exec + GAS_FOR_STORAGE_DEPOSIT.as_tgas() + GAS_FOR_ON_STORAGE_DEPOSIT.as_tgas());
+        assert!(GAS_FOR_ON_CHECK_STORAGE.as_tgas() >= exec + GAS_FOR_FT_TRANSFER.as_tgas());
+        assert!(GAS_FOR_SAFE_FT_TRANSFER.as_tgas() >= GAS_FOR_STORAGE_BALANCE_OF.as_tgas() + GAS_FOR_ON_CHECK_STORAGE.as_tgas());
+    }
+}
@@ -1,4 +1,14 @@
-use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, serde::{Deserialize, Serialize}, NearSchema, NearToken};
+use std::fmt;
+
+use near_sdk::{borsh::{self, BorshDeserialize, BorshSerialize}, env, serde::{Deserialize, Serialize}, NearSchema, NearToken};
+
+use crate::assets::Asset;
+
+// minimum time (seconds) between creating an escrow and the secret being usable on it
+pub const FINALITY_GAP: u32 = 60;
+
+// minimum length (seconds) of every bounded timelock phase
+pub const MIN_PHASE_DURATION: u32 = 60;
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
@@ -7,8 +17,8 @@ pub struct Immutables {
     pub salt: String,                   // random string to distinguish orders                     
     pub order_root_hash: String,        // root_hash of maker order to fill
     pub hashlock: String,               // hash lock of this part of order fill
-    pub making_token: String,           // token used by maker to make exchange
-    pub taking_token: String,           // token that user wants 
+    pub making_token: Asset,            // token used by maker to make exchange
+    pub taking_token: Asset,            // token that user wants 
     pub making_amount: NearToken,       // total tokens maker is putting
     pub taking_amount: NearToken,       // tokens that token is expected to receive
     pub src_safty_deposit: NearToken,   // source chain safty deposit
@@ -16,17 +26,20 @@ pub struct Immutables {
     pub timelock: TimeLock,             // transaction timelocks
     pub maker: String,                  // maker account
     pub taker: String,                  // taker account
+    pub receiver: String,               // maker's account receiving the taking token on the destination chain
 }
 
 
 impl Immutables {
+    // Tokens are hashed as their borsh encoding (variant tag, then length-prefixed fields),
+    // which tells the asset kinds apart and binds the token id of NEP-245 and NEP-171 assets
     pub fn hash(&self) -> String {
         let mut combined = Vec::new();
         combined.extend_from_slice(self.salt.as_bytes());
         combined.extend_from_slice(self.order_root_hash.as_bytes());
         combined.extend_from_slice(self.hashlock.as_bytes());
-        combined.extend_from_slice(self.making_token.as_bytes());
-        combined.extend_from_slice(self.taking_token.as_bytes());
+        combined.extend_from_slice(&borsh::to_vec(&self.making_token).expect("Failed to serialize making token"));
+        combined.extend_from_slice(&borsh::to_vec(&self.taking_token).expect("Failed to serialize taking token"));
         combined.extend_from_slice(&self.making_amount.as_yoctonear().to_be_bytes());
         combined.extend_from_slice(&self.taking_amount.as_yoctonear().to_be_bytes());
         combined.extend_from_slice(&self.src_safty_deposit.as_yoctonear().to_be_bytes());
@@ -34,30 +47,137 @@ impl Immutables {
         combined.extend_from_slice(&self.timelock.get_combined());
         combined.extend_from_slice(self.maker.as_bytes());
         combined.extend_from_slice(self.taker.as_bytes());
+        combined.extend_from_slice(self.receiver.as_bytes());
         let hash = env::keccak256(&combined);
         hex::encode(hash)
     }
 }
 
 
+// Timelocks are 1inch-style relative offsets: every phase is given in seconds after
+// `deployed_at`, which the contract records when the escrow is created. This keeps
+// Immutables::hash() independent of when the client built the immutables.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
 #[borsh(crate = "near_sdk::borsh")]
 pub struct TimeLock {
-    pub src_withdrawal: u64,
-    pub src_public_withdrawal: u64,
-    pub src_cancellation: u64,
-    pub src_public_cancellation: u64,
-    pub dst_withdrawal: u64,
-    pub dst_public_withdrawal: u64,
-    pub dst_cancellation: u64
+    #[serde(default)]
+    pub deployed_at: u32,               // escrow creation in seconds, set by the contract
+    pub src_withdrawal: u32,
+    pub src_public_withdrawal: u32,
+    pub src_cancellation: u32,
+    pub src_public_cancellation: u32,
+    pub dst_withdrawal: u32,
+    pub dst_public_withdrawal: u32,
+    pub dst_cancellation: u32,
+    pub dst_public_cancellation: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    SrcWithdrawal,
+    SrcPublicWithdrawal,
+    SrcCancellation,
+    SrcPublicCancellation,
+    DstWithdrawal,
+    DstPublicWithdrawal,
+    DstCancellation,
+    DstPublicCancellation
 }
 
 impl TimeLock {
-    pub fn verify(&self) -> bool {
-        todo!()
+    /**
+     * @dev Checks that the timelocks describe a valid swap:
+     * - src phases: withdrawal < public withdrawal < cancellation < public cancellation
+     * - dst phases: withdrawal < public withdrawal < cancellation < public cancellation
+     * - every bounded phase lasts at least MIN_PHASE_DURATION
+     * - the dst cancellation offset is below the src one; offsets count from each escrow's own
+     *   deployment, so EscrowDst also checks absolute times with verify_dst_cancellation
+     * - withdrawal on each chain opens at least FINALITY_GAP after that escrow is created
+     */
+    pub fn verify(&self) -> Result<(), TimeLockError> {
+        let src_phases = [
+            ("src_withdrawal", self.src_withdrawal),
+            ("src_public_withdrawal", self.src_public_withdrawal),
+            ("src_cancellation", self.src_cancellation),
+            ("src_public_cancellation", self.src_public_cancellation),
+        ];
+        let dst_phases = [
+            ("dst_withdrawal", self.dst_withdrawal),
+            ("dst_public_withdrawal", self.dst_public_withdrawal),
+            ("dst_cancellation", self.dst_cancellation),
+            ("dst_public_cancellation", self.dst_public_cancellation),
+        ];
+
+        for phases in [src_phases, dst_phases] {
+            for pair in phases.windows(2) {
+                let (phase, start) = pair[0];
+                let (next_phase, end) = pair[1];
+
+                if end <= start {
+                    return Err(TimeLockError::OutOfOrder { phase, next_phase });
+                }
+                if end - start < MIN_PHASE_DURATION {
+                    return Err(TimeLockError::PhaseTooShort { phase, duration: end - start });
+                }
+            }
+        }
+
+        if self.dst_cancellation >= self.src_cancellation {
+            return Err(TimeLockError::DstCancellationNotBeforeSrc);
+        }
+
+        if self.src_withdrawal < FINALITY_GAP {
+            return Err(TimeLockError::FinalityGapTooShort { phase: "src_withdrawal" });
+        }
+        if self.dst_withdrawal < FINALITY_GAP {
+            return Err(TimeLockError::FinalityGapTooShort { phase: "dst_withdrawal" });
+        }
+
+        Ok(())
     }
 
+    /**
+     * @dev Checks a dst escrow deployed at `deployed_at` (seconds) against its src escrow.
+     * The dst escrow is always deployed after the src one, so its cancellation must open no
+     * later than the src escrow's absolute cancellation timestamp (seconds), otherwise the
+     * resolver could cancel on src while the maker still can't cancel on dst.
+     */
+    pub fn verify_dst_cancellation(&self, deployed_at: u32, src_cancellation_timestamp: u32) -> Result<(), TimeLockError> {
+        let dst_cancellation = deployed_at as u64 + self.dst_cancellation as u64;
+        if dst_cancellation > src_cancellation_timestamp as u64 {
+            return Err(TimeLockError::DstCancellationAfterSrcEscrow);
+        }
+
+        Ok(())
+    }
+
+    // Absolute timestamp (nanoseconds) at which the given stage starts
+    pub fn get(&self, stage: Stage) -> u64 {
+        let offset = match stage {
+            Stage::SrcWithdrawal => self.src_withdrawal,
+            Stage::SrcPublicWithdrawal => self.src_public_withdrawal,
+            Stage::SrcCancellation => self.src_cancellation,
+            Stage::SrcPublicCancellation => self.src_public_cancellation,
+            Stage::DstWithdrawal => self.dst_withdrawal,
+            Stage::DstPublicWithdrawal => self.dst_public_withdrawal,
+            Stage::DstCancellation => self.dst_cancellation,
+            Stage::DstPublicCancellation => self.dst_public_cancellation,
+        };
+        (self.deployed_at as u64 + offset as u64) * 1_000_000_000
+    }
+
+    // Anchors the offsets at the current block, called once when the escrow is created
+    pub fn set_deployed_at(&mut self) {
+        self.deployed_at = Self::now();
+    }
+
+    // Current block timestamp in seconds, the unit deployed_at and the offsets use
+    pub fn now() -> u32 {
+        (env::block_timestamp() / 1_000_000_000) as u32
+    }
+
+    // deployed_at is left out, it is only known once the escrow exists
     pub fn get_combined(&self) -> Vec<u8> {
         let mut combined = Vec::new();
         combined.extend_from_slice(&self.src_withdrawal.to_be_bytes());
@@ -67,6 +187,127 @@ impl TimeLock {
         combined.extend_from_slice(&self.dst_withdrawal.to_be_bytes());
         combined.extend_from_slice(&self.dst_public_withdrawal.to_be_bytes());
         combined.extend_from_slice(&self.dst_cancellation.to_be_bytes());
+        combined.extend_from_slice(&self.dst_public_cancellation.to_be_bytes());
         combined
     }
-}
\ No newline at end of file
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeLockError {
+    OutOfOrder { phase: &'static str, next_phase: &'static str },
+    PhaseTooShort { phase: &'static str, duration: u32 },
+    DstCancellationNotBeforeSrc,
+    DstCancellationAfterSrcEscrow,
+    FinalityGapTooShort { phase: &'static str },
+}
+
+impl fmt::Display for TimeLockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeLockError::OutOfOrder { phase, next_phase } =>
+                write!(f, "Invalid timelock: {} must start after {}...", next_phase, phase),
+            TimeLockError::PhaseTooShort { phase, duration } =>
+                write!(f, "Invalid timelock: {} lasts {}s, minimum is {}s...", phase, duration, MIN_PHASE_DURATION),
+            TimeLockError::DstCancellationNotBeforeSrc =>
+                write!(f, "Invalid timelock: dst_cancellation must start before src_cancellation..."),
+            TimeLockError::DstCancellationAfterSrcEscrow =>
+                write!(f, "Invalid timelock: dst cancellation must start no later than the src escrow cancellation..."),
+            TimeLockError::FinalityGapTooShort { phase } =>
+                write!(f, "Invalid timelock: {} must be at least {}s after deployment...", phase, FINALITY_GAP),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timelock() -> TimeLock {
+        TimeLock {
+            deployed_at: 0,
+            src_withdrawal: 120,
+            src_public_withdrawal: 600,
+            src_cancellation: 1_200,
+            src_public_cancellation: 1_800,
+            dst_withdrawal: 60,
+            dst_public_withdrawal: 300,
+            dst_cancellation: 900,
+            dst_public_cancellation: 1_500
+        }
+    }
+
+    #[test]
+    fn valid_timelock_passes() {
+        assert_eq!(timelock().verify(), Ok(()));
+    }
+
+    #[test]
+    fn phases_must_be_ordered() {
+        let mut src = timelock();
+        src.src_public_withdrawal = src.src_withdrawal;
+        assert_eq!(src.verify(), Err(TimeLockError::OutOfOrder { phase: "src_withdrawal", next_phase: "src_public_withdrawal" }));
+
+        let mut dst = timelock();
+        dst.dst_public_cancellation = dst.dst_cancellation - 1;
+        assert_eq!(dst.verify(), Err(TimeLockError::OutOfOrder { phase: "dst_cancellation", next_phase: "dst_public_cancellation" }));
+    }
+
+    #[test]
+    fn phases_must_last_long_enough() {
+        let mut timelock = timelock();
+        timelock.src_public_withdrawal = timelock.src_withdrawal + MIN_PHASE_DURATION - 1;
+        assert_eq!(
+            timelock.verify(),
+            Err(TimeLockError::PhaseTooShort { phase: "src_withdrawal", duration: MIN_PHASE_DURATION - 1 })
+        );
+
+        // exactly the minimum is enough
+        timelock.src_public_withdrawal += 1;
+        assert_eq!(timelock.verify(), Ok(()));
+    }
+
+    #[test]
+    fn dst_cancellation_must_start_before_src() {
+        let mut timelock = timelock();
+        timelock.dst_cancellation = timelock.src_cancellation;
+        timelock.dst_public_cancellation = timelock.src_public_cancellation;
+        assert_eq!(timelock.verify(), Err(TimeLockError::DstCancellationNotBeforeSrc));
+    }
+
+    #[test]
+    fn withdrawals_wait_for_finality() {
+        let mut src = timelock();
+        src.src_withdrawal = FINALITY_GAP - 1;
+        assert_eq!(src.verify(), Err(TimeLockError::FinalityGapTooShort { phase: "src_withdrawal" }));
+
+        let mut dst = timelock();
+        dst.dst_withdrawal = FINALITY_GAP - 1;
+        assert_eq!(dst.verify(), Err(TimeLockError::FinalityGapTooShort { phase: "dst_withdrawal" }));
+    }
+
+    #[test]
+    fn dst_cancellation_is_checked_against_the_src_escrow() {
+        let src_deployed_at = 1_000;
+        let src_cancellation_timestamp = src_deployed_at + timelock().src_cancellation;
+
+        // deployed right after the src escrow: dst cancels at 1_900 + 900 <= 2_200
+        assert_eq!(timelock().verify_dst_cancellation(1_100, src_cancellation_timestamp), Ok(()));
+        // exactly at the src cancellation is still fine
+        assert_eq!(timelock().verify_dst_cancellation(1_300, src_cancellation_timestamp), Ok(()));
+        // offsets pass verify, but deployed too late the dst escrow would cancel after src
+        assert_eq!(timelock().verify(), Ok(()));
+        assert_eq!(
+            timelock().verify_dst_cancellation(1_301, src_cancellation_timestamp),
+            Err(TimeLockError::DstCancellationAfterSrcEscrow)
+        );
+    }
+
+    #[test]
+    fn dst_cancellation_does_not_overflow() {
+        assert_eq!(
+            timelock().verify_dst_cancellation(u32::MAX, u32::MAX),
+            Err(TimeLockError::DstCancellationAfterSrcEscrow)
+        );
+    }
+}
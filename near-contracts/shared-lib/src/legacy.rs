@@ -0,0 +1,53 @@
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, require, serde::{Deserialize, Serialize}, AccountId, NearSchema, NearToken};
+
+// Immutables of the first deployed layout, as escrows funded back then are stored
+// Tokens and accounts are plain strings and timelocks absolute u64 timestamps
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct LegacyImmutables {
+    pub salt: String,
+    pub order_root_hash: String,
+    pub hashlock: String,
+    pub making_token: String,
+    pub taking_token: String,
+    pub making_amount: NearToken,
+    pub taking_amount: NearToken,
+    pub src_safty_deposit: NearToken,
+    pub dst_safty_deposit: NearToken,
+    pub timelock: LegacyTimeLock,
+    pub maker: String,
+    pub taker: String
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct LegacyTimeLock {
+    pub src_withdrawal: u64,
+    pub src_public_withdrawal: u64,
+    pub src_cancellation: u64,
+    pub src_public_cancellation: u64,
+    pub dst_withdrawal: u64,
+    pub dst_public_withdrawal: u64,
+    pub dst_cancellation: u64
+}
+
+impl LegacyImmutables {
+    pub fn maker(&self) -> AccountId {
+        self.maker.parse().expect("Invalid maker account...")
+    }
+
+    pub fn taker(&self) -> AccountId {
+        self.taker.parse().expect("Invalid taker account...")
+    }
+
+    // The first layout never recorded settlements, so the owner tells where a legacy escrow's
+    // tokens go: one of its two parties, nobody else
+    pub fn assert_party(&self, receiver_id: &AccountId) {
+        require!(
+            *receiver_id == self.maker() || *receiver_id == self.taker(),
+            "Receiver must be the maker or the taker..."
+        );
+    }
+}
@@ -1,4 +1,15 @@
 pub mod immutables;
 pub mod merkle_verifier;
 pub mod fungible_tokens;
-pub mod utils;
\ No newline at end of file
+pub mod multi_tokens;
+pub mod non_fungible_tokens;
+pub mod utils;
+pub mod escrow_status;
+pub mod resolver_registry;
+pub mod events;
+pub mod secret_registry;
+pub mod assets;
+pub mod near_wrapper;
+pub mod secondary_index;
+pub mod legacy;
+pub mod storage;
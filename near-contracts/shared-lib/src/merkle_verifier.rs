@@ -63,16 +63,10 @@ impl MerkleVerifier {
         combined.extend_from_slice(&hash_bytes);
 
         let value = env::keccak256(&combined);
-        let value = hex::encode(value);
-
-        value
+        hex::encode(value)
     }
 
     fn strip_0x(hex_string: &str) -> String {
-        if hex_string.starts_with("0x") {
-            hex_string[2..].to_string()
-        } else {
-            hex_string.to_string()
-        }
+        hex_string.strip_prefix("0x").unwrap_or(hex_string).to_string()
     }
 }
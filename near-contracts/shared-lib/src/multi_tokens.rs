@@ -0,0 +1,33 @@
+use near_sdk::{ext_contract, AccountId, NearToken, Promise};
+
+
+// NEP-245 multi token contract (e.g. the intents contract)
+#[ext_contract(ext_mt)]
+pub trait MultiToken {
+    // Transfer tokens of one token id to another account, requires 1 yoctoNEAR
+    fn mt_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        amount: NearToken,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+    );
+
+    // Transfer tokens and call mt_on_transfer on the receiver contract
+    fn mt_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        amount: NearToken,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+        msg: String,
+    ) -> Promise;
+
+    // View balance of an account for one token id
+    fn mt_balance_of(&self, account_id: AccountId, token_id: String) -> NearToken;
+
+    // View balances of an account for several token ids
+    fn mt_batch_balance_of(&self, account_id: AccountId, token_ids: Vec<String>) -> Vec<NearToken>;
+}
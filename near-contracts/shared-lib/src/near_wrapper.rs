@@ -0,0 +1,246 @@
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, ext_contract, log, AccountId, Gas, NearToken, Promise, PromiseError};
+
+use crate::{assets::Asset, fungible_tokens::ext_wrap};
+
+pub const GAS_FOR_NEAR_WRAP: Gas = Gas::from_tgas(10);
+// the wrap callbacks may end in a safe FT transfer
+pub const GAS_FOR_RESOLVE_WRAP: Gas = Gas::from_tgas(75);
+const ONE_YOCTO: NearToken = NearToken::from_yoctonear(1);
+
+// Implemented by each escrow as thin wrappers around NearWrapper::resolve_wrap / resolve_unwrap
+#[ext_contract(ext_wrap_callbacks)]
+pub trait WrapCallbacks {
+    fn on_near_wrapped(&mut self, settlement_id: String, wrap_account: AccountId, receiver_id: AccountId, amount: NearToken) -> Promise;
+
+    fn on_near_unwrapped(&mut self, settlement_id: String, wrap_account: AccountId, receiver_id: AccountId, amount: NearToken) -> Promise;
+}
+
+// What an escrow provides to pay out the result of a conversion
+pub trait NearPayouts {
+    // Pays out an asset the escrow holds the way the escrow pays out that kind of asset
+    fn pay_out(&mut self, asset: &Asset, receiver_id: AccountId, amount: NearToken) -> Promise;
+
+    // The funds of a settlement were converted to `asset` and are about to be paid out.
+    // If that payout fails the contract holds `asset` for the settlement, so it must go on
+    // from there: converting the original asset again would spend other escrows' funds.
+    fn record_conversion(&mut self, settlement_id: &str, asset: Asset);
+}
+
+// wNEAR contract (e.g. wrap.near) NEAR payouts are wrapped/unwrapped with, set by the owner
+// It is only consulted when an escrow is funded: settlement converts through the wNEAR
+// contract named by the escrow's own assets, so changing it never breaks funded escrows
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct NearWrapper {
+    wrap_account: Option<AccountId>
+}
+
+impl NearWrapper {
+    pub fn wrap_account(&self) -> Option<&AccountId> {
+        self.wrap_account.as_ref()
+    }
+
+    pub fn set_wrap_account(&mut self, wrap_account: Option<AccountId>) {
+        self.wrap_account = wrap_account;
+    }
+
+    pub fn is_wrapped_near(&self, asset: &Asset) -> bool {
+        matches!((asset, &self.wrap_account), (Asset::Ft(token), Some(wrap_account)) if token == wrap_account)
+    }
+
+    // Pays out `amount` of the held asset as `payout_asset`, wrapping or unwrapping NEAR on the
+    // way when the two differ. A failed conversion pays out the held asset instead, a successful
+    // one is recorded for `settlement_id` (see NearPayouts::record_conversion).
+    pub fn transfer<C: NearPayouts>(
+        contract: &mut C,
+        held_asset: &Asset,
+        payout_asset: &Asset,
+        settlement_id: String,
+        receiver_id: AccountId,
+        amount: NearToken
+    ) -> Promise {
+        if held_asset == payout_asset {
+            return contract.pay_out(held_asset, receiver_id, amount);
+        }
+
+        Self::convert(held_asset, payout_asset, settlement_id, receiver_id, amount)
+            .unwrap_or_else(|| env::panic_str("Unsupported asset conversion..."))
+    }
+
+    // Starts paying out `amount` of the held asset as the payout asset through the wNEAR
+    // contract on the fungible side of the pair, the current contract's callback sends the result
+    // `settlement_id` names the escrow (or order refund) whose funds are converted
+    // None unless one asset is native NEAR and the other one a fungible token
+    pub fn convert(held_asset: &Asset, payout_asset: &Asset, settlement_id: String, receiver_id: AccountId, amount: NearToken) -> Option<Promise> {
+        let callbacks = ext_wrap_callbacks::ext(env::current_account_id()).with_static_gas(GAS_FOR_RESOLVE_WRAP);
+
+        match (held_asset, payout_asset) {
+            (Asset::Near, Asset::Ft(wrap_account)) => Some(
+                Self::conversion(held_asset, payout_asset, amount)?
+                    .then(callbacks.on_near_wrapped(settlement_id, wrap_account.clone(), receiver_id, amount))
+            ),
+            (Asset::Ft(wrap_account), Asset::Near) => Some(
+                Self::conversion(held_asset, payout_asset, amount)?
+                    .then(callbacks.on_near_unwrapped(settlement_id, wrap_account.clone(), receiver_id, amount))
+            ),
+            _ => None
+        }
+    }
+
+    // Converts `amount` of the held asset back into the original one, without paying anything out
+    pub fn restore(held_asset: &Asset, original_asset: &Asset, amount: NearToken) -> Option<Promise> {
+        Self::conversion(held_asset, original_asset, amount)
+    }
+
+    // Result of near_deposit: pays out the wNEAR, or the NEAR itself if wrapping failed
+    pub fn resolve_wrap<C: NearPayouts>(
+        contract: &mut C,
+        settlement_id: String,
+        wrap_account: AccountId,
+        receiver_id: AccountId,
+        amount: NearToken,
+        wrap_result: Result<(), PromiseError>
+    ) -> Promise {
+        if wrap_result.is_err() {
+            log!("Wrapping {} failed, paying out native NEAR", amount);
+            return contract.pay_out(&Asset::Near, receiver_id, amount);
+        }
+
+        let wrapped = Asset::Ft(wrap_account);
+        contract.record_conversion(&settlement_id, wrapped.clone());
+        contract.pay_out(&wrapped, receiver_id, amount)
+    }
+
+    // Result of near_withdraw: pays out the NEAR, or the wNEAR itself if unwrapping failed
+    pub fn resolve_unwrap<C: NearPayouts>(
+        contract: &mut C,
+        settlement_id: String,
+        wrap_account: AccountId,
+        receiver_id: AccountId,
+        amount: NearToken,
+        unwrap_result: Result<(), PromiseError>
+    ) -> Promise {
+        if unwrap_result.is_err() {
+            log!("Unwrapping {} failed, paying out wNEAR", amount);
+            return contract.pay_out(&Asset::Ft(wrap_account), receiver_id, amount);
+        }
+
+        contract.record_conversion(&settlement_id, Asset::Near);
+        contract.pay_out(&Asset::Near, receiver_id, amount)
+    }
+
+    // near_deposit or near_withdraw call turning `from` into `to`
+    fn conversion(from: &Asset, to: &Asset, amount: NearToken) -> Option<Promise> {
+        match (from, to) {
+            (Asset::Near, Asset::Ft(wrap_account)) => Some(
+                ext_wrap::ext(wrap_account.clone())
+                    .with_attached_deposit(amount)
+                    .with_static_gas(GAS_FOR_NEAR_WRAP)
+                    .near_deposit()
+            ),
+            (Asset::Ft(wrap_account), Asset::Near) => Some(
+                ext_wrap::ext(wrap_account.clone())
+                    .with_attached_deposit(ONE_YOCTO)
+                    .with_static_gas(GAS_FOR_NEAR_WRAP)
+                    .near_withdraw(amount)
+            ),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    use super::*;
+    use crate::fungible_tokens::{GAS_FOR_CALLBACK_EXECUTION, GAS_FOR_SAFE_FT_TRANSFER};
+
+    #[derive(Default)]
+    struct Escrow {
+        held_asset: Option<Asset>,
+        ft_payouts: u32
+    }
+
+    impl NearPayouts for Escrow {
+        fn pay_out(&mut self, asset: &Asset, receiver_id: AccountId, amount: NearToken) -> Promise {
+            if matches!(asset, Asset::Ft(_)) {
+                self.ft_payouts += 1;
+            }
+            Promise::new(receiver_id).transfer(amount)
+        }
+
+        fn record_conversion(&mut self, _settlement_id: &str, asset: Asset) {
+            self.held_asset = Some(asset);
+        }
+    }
+
+    fn account(name: &str) -> AccountId {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn successful_conversions_are_recorded_before_the_payout() {
+        testing_env!(VMContextBuilder::new().build());
+        let amount = NearToken::from_near(1);
+
+        let mut escrow = Escrow::default();
+        NearWrapper::resolve_wrap(&mut escrow, "escrow".to_string(), account("wrap.near"), account("alice.near"), amount, Ok(())).detach();
+        assert_eq!(escrow.held_asset, Some(Asset::Ft(account("wrap.near"))));
+        assert_eq!(escrow.ft_payouts, 1);
+
+        let mut escrow = Escrow::default();
+        NearWrapper::resolve_unwrap(&mut escrow, "escrow".to_string(), account("wrap.near"), account("alice.near"), amount, Ok(())).detach();
+        assert_eq!(escrow.held_asset, Some(Asset::Near));
+        assert_eq!(escrow.ft_payouts, 0);
+    }
+
+    #[test]
+    fn failed_conversions_pay_out_the_held_asset() {
+        testing_env!(VMContextBuilder::new().build());
+        let amount = NearToken::from_near(1);
+
+        let mut escrow = Escrow::default();
+        NearWrapper::resolve_wrap(&mut escrow, "escrow".to_string(), account("wrap.near"), account("alice.near"), amount, Err(PromiseError::Failed)).detach();
+        assert_eq!((escrow.held_asset, escrow.ft_payouts), (None, 0));
+
+        let mut escrow = Escrow::default();
+        NearWrapper::resolve_unwrap(&mut escrow, "escrow".to_string(), account("wrap.near"), account("alice.near"), amount, Err(PromiseError::Failed)).detach();
+        assert_eq!((escrow.held_asset, escrow.ft_payouts), (None, 1));
+    }
+
+    #[test]
+    fn wrap_callbacks_cover_a_safe_ft_transfer() {
+        assert!(GAS_FOR_RESOLVE_WRAP.as_tgas() >= GAS_FOR_CALLBACK_EXECUTION.as_tgas() + GAS_FOR_SAFE_FT_TRANSFER.as_tgas());
+    }
+
+    #[test]
+    fn only_near_and_a_token_convert() {
+        testing_env!(VMContextBuilder::new().build());
+        let wnear = Asset::Ft(account("wrap.near"));
+        let amount = NearToken::from_near(1);
+
+        assert!(NearWrapper::restore(&Asset::Near, &wnear, amount).is_some_and(|promise| { promise.detach(); true }));
+        assert!(NearWrapper::restore(&wnear, &Asset::Near, amount).is_some_and(|promise| { promise.detach(); true }));
+        assert!(NearWrapper::restore(&wnear, &Asset::Ft(account("usdc.near")), amount).is_none());
+        assert!(NearWrapper::convert(&Asset::Near, &Asset::Near, "escrow".to_string(), account("alice.near"), amount).is_none());
+    }
+
+    #[test]
+    fn same_assets_are_paid_out_without_conversion() {
+        testing_env!(VMContextBuilder::new().build());
+        let wnear = Asset::Ft(account("wrap.near"));
+
+        let mut escrow = Escrow::default();
+        NearWrapper::transfer(&mut escrow, &wnear, &wnear, "escrow".to_string(), account("alice.near"), NearToken::from_near(1)).detach();
+        assert_eq!((escrow.held_asset, escrow.ft_payouts), (None, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported asset conversion...")]
+    fn unrelated_assets_can_not_be_converted() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut escrow = Escrow::default();
+        NearWrapper::transfer(&mut escrow, &Asset::Ft(account("usdc.near")), &Asset::Ft(account("wrap.near")), "escrow".to_string(), account("alice.near"), NearToken::from_near(1)).detach();
+    }
+}
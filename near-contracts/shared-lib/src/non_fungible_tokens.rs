@@ -0,0 +1,25 @@
+use near_sdk::{ext_contract, AccountId, Promise};
+
+
+// NEP-171 non-fungible token contract
+#[ext_contract(ext_nft)]
+pub trait NonFungibleToken {
+    // Transfer a token to another account, requires 1 yoctoNEAR
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    );
+
+    // Transfer a token and call nft_on_transfer on the receiver contract
+    fn nft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+    ) -> Promise;
+}
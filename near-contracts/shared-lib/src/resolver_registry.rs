@@ -0,0 +1,72 @@
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, require, serde::{Deserialize, Serialize}, store::IterableMap, AccountId, IntoStorageKey, NearSchema};
+
+use crate::utils::page;
+
+// Allowlist of resolvers managed by an owner
+// Only enabled resolvers may take orders; public withdraw/cancel paths don't consult it
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct ResolverRegistry {
+    owner: AccountId,
+    resolvers: IterableMap<AccountId, bool>     // resolver -> enabled
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ResolverInfo {
+    pub account_id: AccountId,
+    pub enabled: bool
+}
+
+impl ResolverRegistry {
+    pub fn new<S: IntoStorageKey>(prefix: S, owner: AccountId) -> Self {
+        Self {
+            owner,
+            resolvers: IterableMap::new(prefix)
+        }
+    }
+
+    pub fn owner(&self) -> &AccountId {
+        &self.owner
+    }
+
+    pub fn assert_owner(&self) {
+        require!(env::predecessor_account_id() == self.owner, "Only owner can manage resolvers...");
+    }
+
+    pub fn set_owner(&mut self, owner: AccountId) {
+        self.assert_owner();
+        self.owner = owner;
+    }
+
+    // Registers a resolver (enabled), or re-enables an already registered one
+    pub fn add(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.resolvers.insert(account_id, true);
+    }
+
+    pub fn remove(&mut self, account_id: &AccountId) {
+        self.assert_owner();
+        require!(self.resolvers.remove(account_id).is_some(), "Resolver is not registered...");
+    }
+
+    pub fn set_enabled(&mut self, account_id: &AccountId, enabled: bool) {
+        self.assert_owner();
+        let entry = self.resolvers.get_mut(account_id).expect("Resolver is not registered...");
+        *entry = enabled;
+    }
+
+    pub fn is_active(&self, account_id: &AccountId) -> bool {
+        self.resolvers.get(account_id).copied().unwrap_or(false)
+    }
+
+    pub fn assert_active(&self, account_id: &AccountId) {
+        require!(self.is_active(account_id), "Resolver is not registered or disabled...");
+    }
+
+    pub fn list(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<ResolverInfo> {
+        page(self.resolvers.iter(), from_index, limit)
+            .map(|(account_id, enabled)| ResolverInfo { account_id: account_id.clone(), enabled: *enabled })
+            .collect()
+    }
+}
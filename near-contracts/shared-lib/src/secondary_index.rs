@@ -0,0 +1,71 @@
+use near_sdk::{borsh::{self, BorshDeserialize, BorshSerialize}, env, store::{IterableSet, LookupMap}, IntoStorageKey};
+
+// Ids (order root hashes, immutables hashes) grouped by a key such as the maker, the taker or
+// the token, so views page through the entries of one key instead of scanning a whole map
+// Each key's set lives under prefix + sha256(borsh(key)), which can't collide between keys
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SecondaryIndex<K: BorshSerialize + Ord> {
+    prefix: Vec<u8>,
+    entries: LookupMap<K, IterableSet<String>>
+}
+
+impl<K: BorshSerialize + BorshDeserialize + Ord + Clone> SecondaryIndex<K> {
+    pub fn new<S: IntoStorageKey>(prefix: S) -> Self {
+        let prefix = prefix.into_storage_key();
+        Self {
+            entries: LookupMap::new([prefix.as_slice(), b"k"].concat()),
+            prefix
+        }
+    }
+
+    pub fn insert(&mut self, key: &K, id: String) {
+        let prefix = [self.prefix.as_slice(), b"s", &Self::key_hash(key)].concat();
+        self.entries.entry(key.clone())
+            .or_insert_with(|| IterableSet::new(prefix))
+            .insert(id);
+    }
+
+    // Drops the id, and the key's set once it is empty
+    pub fn remove(&mut self, key: &K, id: &str) {
+        let Some(ids) = self.entries.get_mut(key) else {
+            return;
+        };
+
+        ids.remove(id);
+        if ids.is_empty() {
+            self.entries.remove(key);
+        }
+    }
+
+    pub fn iter(&self, key: &K) -> impl Iterator<Item = &String> {
+        self.entries.get(key).into_iter().flat_map(|ids| ids.iter())
+    }
+
+    fn key_hash(key: &K) -> Vec<u8> {
+        env::sha256(borsh::to_vec(key).expect("Failed to serialize index key"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::AccountId;
+
+    use super::*;
+
+    #[test]
+    fn keys_keep_separate_sets() {
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut index = SecondaryIndex::new(b"i");
+
+        index.insert(&alice, "a1".to_string());
+        index.insert(&alice, "a2".to_string());
+        index.insert(&bob, "b1".to_string());
+        index.remove(&alice, "a1");
+        index.remove(&bob, "b1");
+
+        assert_eq!(index.iter(&alice).collect::<Vec<_>>(), vec!["a2"]);
+        assert_eq!(index.iter(&bob).count(), 0);
+    }
+}
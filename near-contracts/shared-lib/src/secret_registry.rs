@@ -0,0 +1,158 @@
+use near_sdk::{borsh::{BorshDeserialize, BorshSerialize}, env, require, serde::{Deserialize, Serialize}, store::{IterableMap, LookupMap}, IntoStorageKey, NearSchema};
+
+use crate::{immutables::Immutables, utils::{page, validate_secret}};
+
+// Secrets revealed by withdrawals, so relayers don't have to scrape transaction arguments
+// and later settlements on the same hashlock don't need the secret passed again
+// Retention: a secret outlives the settlement of the escrows that revealed it. It can only be
+// pruned (by anyone) once all of them have reached their cancellation stage, after which none
+// of them can be withdrawn anymore; the secret stays public in the `withdrawn` event for good.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SecretRegistry {
+    secrets: IterableMap<String, String>,               // hashlock (no 0x) -> secret
+    escrow_hashlocks: LookupMap<String, String>,        // immutables hash -> hashlock (no 0x)
+    retentions: LookupMap<String, SecretRetention>      // hashlock (no 0x) -> escrows that revealed it
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+struct SecretRetention {
+    prunable_at: u64,           // latest cancellation start (nanoseconds) of the escrows below
+    escrows: Vec<String>        // immutables hashes of the escrows that revealed the secret
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RevealedSecret {
+    pub hashlock: String,
+    pub secret: String
+}
+
+impl SecretRegistry {
+    pub fn new<S: IntoStorageKey>(prefix: S) -> Self {
+        let prefix = prefix.into_storage_key();
+        Self {
+            secrets: IterableMap::new([prefix.as_slice(), b"s"].concat()),
+            escrow_hashlocks: LookupMap::new([prefix.as_slice(), b"e"].concat()),
+            retentions: LookupMap::new([prefix.as_slice(), b"r"].concat())
+        }
+    }
+
+    // Secret for a withdrawal: the given one, or the one already revealed for the hashlock.
+    // Panics if it doesn't match the hashlock.
+    pub fn resolve(&self, secret: Option<String>, hashlock: &str) -> String {
+        let secret = secret.or_else(|| self.get(hashlock)).expect("Secret not provided nor revealed...");
+        require!(validate_secret(secret.clone(), hashlock.to_string()), "Invalid secret...");
+        secret
+    }
+
+    // Validates the secret an escrow is withdrawn with (given, or already revealed) and records it,
+    // keeping it until the escrow's cancellation stage starts at `cancellation_at` (nanoseconds)
+    pub fn reveal(&mut self, secret: Option<String>, immutables: &Immutables, cancellation_at: u64) -> String {
+        let secret = self.resolve(secret, &immutables.hashlock);
+        self.record(&immutables.hashlock, immutables.hash(), secret.clone(), cancellation_at);
+        secret
+    }
+
+    // Stores a validated secret for its hashlock and the escrow that revealed it, which can be
+    // withdrawn until `cancellation_at` (nanoseconds): the secret is kept at least that long
+    pub fn record(&mut self, hashlock: &str, immutables_hash: String, secret: String, cancellation_at: u64) {
+        let hashlock = Self::key(hashlock);
+        let retention = self.retentions.entry(hashlock.clone())
+            .or_insert(SecretRetention { prunable_at: 0, escrows: Vec::new() });
+        retention.prunable_at = retention.prunable_at.max(cancellation_at);
+        if self.escrow_hashlocks.insert(immutables_hash.clone(), hashlock.clone()).is_none() {
+            retention.escrows.push(immutables_hash);
+        }
+        if !self.secrets.contains_key(&hashlock) {
+            self.secrets.insert(hashlock, secret);
+        }
+    }
+
+    // Forgets a secret and its escrows once none of them can be withdrawn anymore
+    // Returns false if the secret is unknown or still retained
+    pub fn prune(&mut self, hashlock: &str) -> bool {
+        let hashlock = Self::key(hashlock);
+        let Some(retention) = self.retentions.get(&hashlock) else {
+            return false;
+        };
+        if env::block_timestamp() < retention.prunable_at {
+            return false;
+        }
+
+        let retention = self.retentions.remove(&hashlock).unwrap();
+        for immutables_hash in retention.escrows {
+            self.escrow_hashlocks.remove(&immutables_hash);
+        }
+        self.secrets.remove(&hashlock);
+        true
+    }
+
+    // Prunes every hashlock that can be pruned, returns how many were
+    pub fn prune_all(&mut self, hashlocks: &[String]) -> u32 {
+        hashlocks.iter().filter(|hashlock| self.prune(hashlock)).count() as u32
+    }
+
+    // Timestamp (nanoseconds) from which the secret of a hashlock can be pruned
+    pub fn prunable_at(&self, hashlock: &str) -> Option<u64> {
+        self.retentions.get(&Self::key(hashlock)).map(|retention| retention.prunable_at)
+    }
+
+    pub fn get(&self, hashlock: &str) -> Option<String> {
+        self.secrets.get(&Self::key(hashlock)).cloned()
+    }
+
+    pub fn get_by_escrow(&self, immutables_hash: &str) -> Option<String> {
+        let hashlock = self.escrow_hashlocks.get(immutables_hash)?;
+        self.secrets.get(hashlock).cloned()
+    }
+
+    pub fn list(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<RevealedSecret> {
+        page(self.secrets.iter(), from_index, limit)
+            .map(|(hashlock, secret)| RevealedSecret { hashlock: hashlock.clone(), secret: secret.clone() })
+            .collect()
+    }
+
+    fn key(hashlock: &str) -> String {
+        hashlock.strip_prefix("0x").unwrap_or(hashlock).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    use super::*;
+
+    fn set_time(timestamp: u64) {
+        testing_env!(VMContextBuilder::new().block_timestamp(timestamp).build());
+    }
+
+    #[test]
+    fn secret_outlives_its_escrows_until_their_cancellation() {
+        set_time(0);
+        let mut registry = SecretRegistry::new(b"x");
+        registry.record("0xabcd", "escrow-1".to_string(), "secret".to_string(), 200);
+        registry.record("abcd", "escrow-2".to_string(), "secret".to_string(), 100);
+        // a retried withdrawal records its escrow only once
+        registry.record("abcd", "escrow-2".to_string(), "secret".to_string(), 100);
+        assert_eq!(registry.prunable_at("0xabcd"), Some(200));
+
+        // the later cancellation of both escrows counts
+        set_time(150);
+        assert!(!registry.prune("abcd"));
+        assert_eq!(registry.get("abcd"), Some("secret".to_string()));
+        assert_eq!(registry.get_by_escrow("escrow-2"), Some("secret".to_string()));
+
+        set_time(200);
+        assert!(registry.prune("0xabcd"));
+        assert_eq!(registry.get("abcd"), None);
+        assert_eq!(registry.get_by_escrow("escrow-1"), None);
+        assert_eq!(registry.get_by_escrow("escrow-2"), None);
+        assert!(registry.list(None, None).is_empty());
+
+        // pruning twice or an unknown hashlock changes nothing
+        assert_eq!(registry.prune_all(&["abcd".to_string(), "ef01".to_string()]), 0);
+    }
+}
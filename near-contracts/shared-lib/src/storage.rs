@@ -0,0 +1,88 @@
+use near_sdk::{env, require, NearToken, Promise, StorageUsage};
+
+// Settles the storage a call changed since `initial_usage` with its caller: bytes added are paid
+// from the attached deposit, bytes freed are paid back, and what's left of the deposit is refunded
+// Collections caching their writes (store::LookupMap) must be flushed before this is called
+pub fn settle_storage(initial_usage: StorageUsage) {
+    let deposit = env::attached_deposit();
+    let usage = env::storage_usage();
+
+    let refund = if usage >= initial_usage {
+        let cost = storage_cost(usage - initial_usage);
+        require!(deposit >= cost, "Insufficient deposit to cover storage...");
+        deposit.saturating_sub(cost)
+    } else {
+        deposit.saturating_add(storage_cost(initial_usage - usage))
+    };
+
+    if !refund.is_zero() {
+        Promise::new(env::predecessor_account_id()).transfer(refund).detach();
+    }
+}
+
+pub fn storage_cost(bytes: StorageUsage) -> NearToken {
+    env::storage_byte_cost().saturating_mul(bytes as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{mock::MockAction, test_utils::{get_created_receipts, VMContextBuilder}, testing_env};
+
+    use super::*;
+
+    fn set_deposit(deposit: NearToken) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("maker.near".parse().unwrap())
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    fn refunds() -> Vec<NearToken> {
+        get_created_receipts().into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                MockAction::Transfer { deposit, .. } => Some(deposit),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn added_storage_is_paid_and_the_rest_refunded() {
+        set_deposit(NearToken::from_near(1));
+        let initial_usage = env::storage_usage();
+        env::storage_write(b"key", &[0; 100]);
+        let cost = storage_cost(env::storage_usage() - initial_usage);
+
+        settle_storage(initial_usage);
+        assert_eq!(refunds(), vec![NearToken::from_near(1).saturating_sub(cost)]);
+    }
+
+    #[test]
+    fn freed_storage_is_paid_back() {
+        set_deposit(NearToken::from_yoctonear(0));
+        env::storage_write(b"key", &[0; 100]);
+        let initial_usage = env::storage_usage();
+        env::storage_remove(b"key");
+
+        settle_storage(initial_usage);
+        assert_eq!(refunds(), vec![storage_cost(initial_usage - env::storage_usage())]);
+    }
+
+    #[test]
+    fn exact_deposit_refunds_nothing() {
+        set_deposit(NearToken::from_yoctonear(0));
+        let initial_usage = env::storage_usage();
+        settle_storage(initial_usage);
+        assert!(refunds().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient deposit to cover storage...")]
+    fn missing_deposit_is_rejected() {
+        set_deposit(NearToken::from_yoctonear(1));
+        let initial_usage = env::storage_usage();
+        env::storage_write(b"key", &[0; 100]);
+        settle_storage(initial_usage);
+    }
+}
@@ -1,11 +1,14 @@
 use near_sdk::{env};
 
-pub fn _only_after(timestamp: u64) -> bool {
-    env::block_timestamp() > timestamp
+use crate::immutables::{Stage, TimeLock};
+
+// Effective deadlines are resolved from the escrow's deployed_at and the stage offset
+pub fn _only_after(timelock: &TimeLock, stage: Stage) -> bool {
+    env::block_timestamp() > timelock.get(stage)
 }
 
-pub fn _only_before(timestamp: u64) -> bool {
-    env::block_timestamp() < timestamp
+pub fn _only_before(timelock: &TimeLock, stage: Stage) -> bool {
+    env::block_timestamp() < timelock.get(stage)
 }
 
 pub fn validate_secret(secret: String, hashlock: String) -> bool {
@@ -16,4 +19,120 @@ pub fn validate_secret(secret: String, hashlock: String) -> bool {
         false => hashlock.to_string()
     };
     hash_hex == hashlock
-}
\ No newline at end of file
+}
+
+// A hashlock is a keccak256 hash as lowercase hex (64 chars), optionally 0x prefixed,
+// the only format validate_secret can ever match
+pub fn is_valid_hashlock(hashlock: &str) -> bool {
+    let hashlock = hashlock.strip_prefix("0x").unwrap_or(hashlock);
+    hashlock.len() == 64 && hashlock.bytes().all(|c| c.is_ascii_digit() || (b'a'..=b'f').contains(&c))
+}
+
+// Page size of the list views when the caller doesn't pass a limit
+pub const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+// Page of a list view: skips `from_index` items (0 by default) and takes at most `limit`
+pub fn page<I: Iterator>(items: I, from_index: Option<u32>, limit: Option<u32>) -> impl Iterator<Item = I::Item> {
+    items
+        .skip(from_index.unwrap_or(0) as usize)
+        .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+}
+
+// Computes a * b / denominator with a 256-bit intermediate product, rounding the result
+// up when `round_up` is set. Returns None on division by zero or if the result overflows u128.
+pub fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+
+    let (high, low) = mul_wide(a, b);
+    if high >= denominator {
+        return None;
+    }
+
+    // long division of the (high, low) product, one bit at a time
+    let mut remainder = high;
+    let mut quotient: u128 = 0;
+    for i in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> i) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+
+    if round_up && remainder > 0 {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+// Full 256-bit product of two u128 values as (high, low) halves
+fn mul_wide(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+
+    let low_low = a_low * b_low;
+    let low_high = a_low * b_high;
+    let high_low = a_high * b_low;
+    let high_high = a_high * b_high;
+
+    let middle = (low_low >> 64) + (low_high & MASK) + (high_low & MASK);
+    let low = (low_low & MASK) | (middle << 64);
+    let high = high_high + (low_high >> 64) + (high_low >> 64) + (middle >> 64);
+
+    (high, low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_default_to_the_first_fifty_items() {
+        assert_eq!(page(0..100, None, None).collect::<Vec<_>>(), (0..50).collect::<Vec<_>>());
+        assert_eq!(page(0..100, Some(95), None).collect::<Vec<_>>(), (95..100).collect::<Vec<_>>());
+        assert_eq!(page(0..100, Some(10), Some(2)).collect::<Vec<_>>(), vec![10, 11]);
+    }
+
+    #[test]
+    fn mul_wide_returns_the_full_product() {
+        assert_eq!(mul_wide(0, u128::MAX), (0, 0));
+        assert_eq!(mul_wide(u64::MAX as u128 + 1, u64::MAX as u128 + 1), (1, 0));
+        // (2^128 - 1)^2 = 2^256 - 2^129 + 1
+        assert_eq!(mul_wide(u128::MAX, u128::MAX), (u128::MAX - 1, 1));
+    }
+
+    #[test]
+    fn mul_div_rounds_as_asked() {
+        assert_eq!(mul_div(10, 10, 3, false), Some(33));
+        assert_eq!(mul_div(10, 10, 3, true), Some(34));
+        // exact results are never rounded up
+        assert_eq!(mul_div(10, 9, 3, true), Some(30));
+    }
+
+    #[test]
+    fn mul_div_uses_a_wide_intermediate() {
+        // the product overflows u128, the result doesn't
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, false), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 1 << 64, 1 << 65, false), Some(u128::MAX >> 1));
+        assert_eq!(mul_div(u128::MAX, 1 << 64, 1 << 65, true), Some((u128::MAX >> 1) + 1));
+    }
+
+    #[test]
+    fn mul_div_rejects_overflow_and_zero_denominator() {
+        assert_eq!(mul_div(1, 1, 0, false), None);
+        assert_eq!(mul_div(u128::MAX, 2, 1, false), None);
+        assert_eq!(mul_div(u128::MAX, 3, 2, false), None);
+
+        // 7 * b = 2 * u128::MAX + 1: the floor is u128::MAX, rounding it up overflows
+        let b = 97_223_533_405_982_418_132_392_744_980_505_203_273;
+        assert_eq!(mul_div(7, b, 2, false), Some(u128::MAX));
+        assert_eq!(mul_div(7, b, 2, true), None);
+    }
+}
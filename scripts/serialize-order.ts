@@ -10,26 +10,63 @@ if (rootHash.startsWith("0x")) {
 
 const makerOrder = {
     root_hash: rootHash, // Use the actual hash
-    token: "mayank-token-1.testnet",
+    token: { Ft: "mayank-token-1.testnet" }, // or { Near: {} }, { Mt: { contract, token_id } }, { Nft: { contract, token_id } }
     total_amount: "1000000000000000000000000", // 1 token (assuming 24 decimals)
     parts: 1,
     filled_amount: "0",
     withdrawn_amount: "0",
+    cancelled_amount: "0",
     maker: "mayank-hello-world.testnet",
-    expiration: (Date.now() + 86400000) * 1000000 // 24 hours from now in nanoseconds
+    expiration: (Date.now() + 86400000) * 1000000, // 24 hours from now in nanoseconds
+    taking_token: { Foreign: "0xdAC17F958D2ee523a2206206994597C13D831ec7" }, // token wanted on the destination chain
+    receiver: "0x000000000000000000000000000000000000dEaD", // maker's address on the destination chain
+    min_taking_amount: "1000000", // least amount accepted for the whole order
+    auction: null, // or { start_time, duration, start_rate, end_rate, points: [{ delay, rate }] }
+    epoch: 0, // maker's current epoch (get_maker_epoch)
+    exclusive_resolver: null, // or { resolver, until } to let a single resolver fill first
+    convert_near: false // NEAR orders pay resolvers wNEAR, wNEAR orders refund the maker NEAR
+};
+
+// Borsh schema for shared_lib::assets::Asset, variants in declaration order
+const assetSchema = {
+  enum: [
+    { struct: { Near: { struct: {} } } },
+    { struct: { Ft: 'string' } },
+    { struct: { Mt: { struct: { contract: 'string', token_id: 'string' } } } },
+    { struct: { Nft: { struct: { contract: 'string', token_id: 'string' } } } },
+    { struct: { Foreign: 'string' } }
+  ]
 };
 
 // Borsh schema for MakerOrder
 const makerOrderSchema = {
   struct: {
     root_hash: 'string',
-    token: 'string',
+    token: assetSchema,
     total_amount: 'u128',
     parts: 'u16',
     filled_amount: 'u128',
     withdrawn_amount: 'u128',
+    cancelled_amount: 'u128',
     maker: 'string',
-    expiration: 'u64'
+    expiration: 'u64',
+    taking_token: assetSchema,
+    receiver: 'string',
+    min_taking_amount: 'u128',
+    auction: {
+      option: {
+        struct: {
+          start_time: 'u64',
+          duration: 'u64',
+          start_rate: 'u32',
+          end_rate: 'u32',
+          points: { array: { type: { struct: { delay: 'u64', rate: 'u32' } } } }
+        }
+      }
+    },
+    epoch: 'u64',
+    exclusive_resolver: { option: { struct: { resolver: 'string', until: 'u64' } } },
+    convert_near: 'bool'
   }
 };
 